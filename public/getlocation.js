@@ -2,13 +2,12 @@ function reloadWithLocationAndOffset(pos) {
   let coords = pos.coords;
   let latlon =
     "lat=" + coords.latitude.toString() + "&lon=" + coords.longitude.toString();
-  let newUrl = "/?" + latlon + "&offset=" + getLocalTimezoneOffset();
+  let newUrl = "/?" + latlon + "&" + getLocalTimezoneParams();
   window.location.replace(newUrl);
 }
 
 function reloadWithOffset() {
-  let offset = getLocalTimezoneOffset();
-  window.location.replace("/?offset=" + offset);
+  window.location.replace("/?" + getLocalTimezoneParams());
 }
 
 function getLocationAndReload() {
@@ -25,9 +24,35 @@ function getLocalTimezoneOffset() {
   return date.getTimezoneOffset();
 }
 
+function getLocalTimezoneName() {
+  try {
+    return Intl.DateTimeFormat().resolvedOptions().timeZone;
+  } catch (e) {
+    return undefined;
+  }
+}
+
+// The offset is kept alongside the zone name for browsers without Intl.
+function getLocalTimezoneParams() {
+  let params = "offset=" + getLocalTimezoneOffset();
+  let name = getLocalTimezoneName();
+  if (name) {
+    params += "&tz=" + encodeURIComponent(name);
+  }
+  return params;
+}
+
+// Station pages are bookmarkable, so only the home page asks for a location.
 window.onload = function() {
-  if (!window.location.search) {
+  if (window.location.search) {
+    return;
+  }
+  if (window.location.pathname === "/") {
     getLocationAndReload();
+  } else {
+    window.location.replace(
+      window.location.pathname + "?" + getLocalTimezoneParams()
+    );
   }
 };
 
@@ -0,0 +1,121 @@
+use chrono::prelude::*;
+use serde::Serialize;
+use uom::si::length::meter;
+use uuid::Uuid;
+
+use crate::compute;
+use crate::model::{Coordinates, TidePrediction};
+use crate::pages::HomePageViewModel;
+use crate::stations::Station;
+use crate::zone::Zone;
+
+/// Machine readable version of the information shown on the home page.
+///
+/// Lengths are always reported in meters and times as RFC 3339 strings
+/// in the zone that was requested.
+#[derive(Debug, Serialize)]
+pub struct TideResponse {
+    pub current_time: DateTime<FixedOffset>,
+    pub time_zone: String,
+    pub location: Option<CoordinatesResponse>,
+    pub station: StationResponse,
+    pub substitution: Option<SubstitutionResponse>,
+    pub distance_to_station_m: Option<f64>,
+    pub tide_is_coming_in: Option<bool>,
+    pub current_level_m: Option<f64>,
+    pub previous: Option<PredictionResponse>,
+    pub next: Option<PredictionResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoordinatesResponse {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StationResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub coordinates: CoordinatesResponse,
+    pub time_zone: String,
+}
+
+/// Present when the nearest station was skipped for one farther away.
+#[derive(Debug, Serialize)]
+pub struct SubstitutionResponse {
+    pub nearest_station: StationResponse,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PredictionResponse {
+    pub tide_type: String,
+    pub level_m: f64,
+    pub time: DateTime<FixedOffset>,
+    pub station_time: DateTime<FixedOffset>,
+}
+
+impl From<&Coordinates> for CoordinatesResponse {
+    fn from(c: &Coordinates) -> Self {
+        CoordinatesResponse {
+            lat: c.lat,
+            lon: c.lon,
+        }
+    }
+}
+
+impl From<&Station> for StationResponse {
+    fn from(station: &Station) -> Self {
+        StationResponse {
+            id: station.id,
+            name: station.name.clone(),
+            coordinates: CoordinatesResponse::from(&station.coordinates),
+            time_zone: station.zone().name(),
+        }
+    }
+}
+
+impl PredictionResponse {
+    fn new(tide_type: &str, prediction: &TidePrediction, station_zone: &Zone) -> Self {
+        PredictionResponse {
+            tide_type: tide_type.to_string(),
+            level_m: prediction.tide.get::<meter>(),
+            time: prediction.time,
+            station_time: station_zone.localize(&prediction.time),
+        }
+    }
+}
+
+impl TideResponse {
+    /// Build the response from the same view model that drives the home page,
+    /// so both always agree on station and predictions.
+    pub fn new(vm: &HomePageViewModel) -> Self {
+        let pair = vm.prediction_pair;
+        TideResponse {
+            current_time: vm.current_time,
+            time_zone: vm.zone.name(),
+            location: vm.current_location.as_ref().map(CoordinatesResponse::from),
+            station: StationResponse::from(&vm.station),
+            substitution: vm.substitution.as_ref().map(|s| SubstitutionResponse {
+                nearest_station: StationResponse::from(&s.nearest),
+                reason: s.reason.to_string(),
+            }),
+            distance_to_station_m: vm
+                .current_location
+                .map(|_| vm.distance_from_station().get::<meter>()),
+            tide_is_coming_in: pair.map(|p| p.tide_is_coming_in()),
+            current_level_m: pair.map(|p| {
+                compute::find::approximate_current_level(&p, &vm.current_time).get::<meter>()
+            }),
+            previous: pair
+                .map(|p| PredictionResponse::new(p.prev_tide_type(), &p.prev, &vm.station_zone)),
+            next: pair
+                .map(|p| PredictionResponse::new(p.next_tide_type(), &p.next, &vm.station_zone)),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("TideResponse should always serialize")
+    }
+}
@@ -0,0 +1,156 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use wtiirn::import::{self, Format, ImportOptions, StationInfo};
+use wtiirn::model::Coordinates;
+use wtiirn::zone::Zone;
+
+static USAGE: &str = "\
+Convert tide agency files into the JSON files wtiirn loads from data/.
+
+Usage: wtiirn-import --format FORMAT [OPTIONS] FILE...
+
+Formats:
+    noaa-xml     NOAA annual high/low prediction XML files
+    noaa-csv     CSV from NOAA's CO-OPS data API
+    noaa-json    JSON from NOAA's CO-OPS data API
+    chs-json     JSON predictions from the DFO/CHS IWLS API
+
+Options:
+    --out DIR                 Data directory to write to (default: data)
+    --prefix PREFIX           File name prefix (default: usa_noaa or ca_dfo)
+    --station-id ID           The agency's station id, e.g. 9414290 or 07795
+    --name NAME               Station name
+    --lat LAT --lon LON       Station location
+    --station-time-zone TZ    IANA time zone of the station
+    --units UNITS             NOAA API units: english or metric (default: metric)
+    --time-zone TZ            NOAA API time zone: gmt, lst or lst_ldt (default: gmt)
+
+The station options are required for every format except noaa-xml.";
+
+struct Args {
+    options: ImportOptions,
+    out: PathBuf,
+    prefix: String,
+    files: Vec<PathBuf>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut imports = vec![];
+    let mut failed = false;
+    for file in &args.files {
+        let result = fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|src| import::import(&src, &args.options).map_err(|e| e.to_string()));
+        match result {
+            Ok(imported) => {
+                println!(
+                    "{}: {} predictions for {} ({})",
+                    file.display(),
+                    imported.predictions.predictions.len(),
+                    imported.station.name,
+                    imported.station.id
+                );
+                imports.push(imported);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    if let Err(e) = import::write_catalogue(&args.out, &args.prefix, &imports) {
+        eprintln!("Could not write to {}: {}", args.out.display(), e);
+        process::exit(1);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut format = None;
+    let mut out = PathBuf::from("data");
+    let mut prefix = None;
+    let mut station_id = None;
+    let mut name = None;
+    let mut lat = None;
+    let mut lon = None;
+    let mut station_time_zone = None;
+    let mut units = None;
+    let mut time_zone = None;
+    let mut files = vec![];
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--format" => format = Some(value()?.parse::<Format>()?),
+            "--out" => out = PathBuf::from(value()?),
+            "--prefix" => prefix = Some(value()?),
+            "--station-id" => {
+                let id = value()?;
+                station_id = Some(
+                    id.parse::<u32>()
+                        .map_err(|_| format!("invalid station id {:?}", id))?,
+                )
+            }
+            "--name" => name = Some(value()?),
+            "--lat" => lat = Some(parse_degrees(&value()?)?),
+            "--lon" => lon = Some(parse_degrees(&value()?)?),
+            "--station-time-zone" => {
+                let tz = value()?;
+                if Zone::from_name(&tz).is_none() {
+                    return Err(format!("unknown time zone {:?}", tz));
+                }
+                station_time_zone = Some(tz)
+            }
+            "--units" => units = Some(value()?),
+            "--time-zone" => time_zone = Some(value()?),
+            "-h" | "--help" => return Err(String::new()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+
+    let format = format.ok_or("--format is required")?;
+    if files.is_empty() {
+        return Err("no files to import".to_string());
+    }
+    let mut options = ImportOptions::new(format);
+    options.units = units.unwrap_or(options.units);
+    options.time_zone = time_zone.unwrap_or(options.time_zone);
+    options.station_time_zone = station_time_zone;
+    options.station = match (station_id, name, lat, lon) {
+        (Some(source_id), Some(name), Some(lat), Some(lon)) => Some(StationInfo {
+            source_id,
+            name,
+            coordinates: Coordinates { lat, lon },
+        }),
+        (None, None, None, None) => None,
+        _ => return Err("--station-id, --name, --lat and --lon go together".to_string()),
+    };
+
+    Ok(Args {
+        prefix: prefix.unwrap_or_else(|| format.default_prefix().to_string()),
+        options,
+        out,
+        files,
+    })
+}
+
+fn parse_degrees(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid coordinate {:?}", value))
+}
@@ -0,0 +1,27 @@
+use std::process;
+use wtiirn::config::Config;
+use wtiirn::stations::{validate, StationCatalogue};
+
+/// Load the catalogue the way the server does and report anything wrong
+/// with it, exiting with an error if there was.
+fn main() {
+    let config = match Config::from_environment() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    let (catalogue, report) = StationCatalogue::load_with_report(&config.data);
+    print!("{}", report);
+
+    let problems = validate::validate(&catalogue);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    println!("Found {} problems", problems.len());
+
+    if !report.is_clean() || !problems.is_empty() {
+        process::exit(1);
+    }
+}
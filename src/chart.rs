@@ -0,0 +1,305 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use itertools::Itertools;
+use std::fmt::Write;
+use uom::si::length::meter;
+
+use crate::compute;
+use crate::compute::sun::Daylight;
+use crate::model::{Coordinates, TidePrediction};
+use crate::zone::Zone;
+
+const WIDTH: f64 = 720.0;
+const HEIGHT: f64 = 240.0;
+const MARGIN_LEFT: f64 = 40.0;
+const MARGIN_RIGHT: f64 = 10.0;
+const MARGIN_TOP: f64 = 24.0;
+const MARGIN_BOTTOM: f64 = 24.0;
+
+/// How much of the past and future the chart covers, relative to now.
+const HOURS_BEFORE: i64 = 3;
+const HOURS_AFTER: i64 = 45;
+const SAMPLE_MINUTES: i64 = 10;
+
+/// Maps times and levels onto the drawing area of the chart.
+struct Scale {
+    zone: Zone,
+    start: DateTime<FixedOffset>,
+    end: DateTime<FixedOffset>,
+    min: f64,
+    max: f64,
+}
+
+impl Scale {
+    fn x<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> f64 {
+        let elapsed = (time.timestamp() - self.start.timestamp()) as f64;
+        let total = (self.end.timestamp() - self.start.timestamp()) as f64;
+        MARGIN_LEFT + elapsed / total * (WIDTH - MARGIN_LEFT - MARGIN_RIGHT)
+    }
+
+    fn y(&self, level: f64) -> f64 {
+        MARGIN_TOP
+            + (self.max - level) / (self.max - self.min) * (HEIGHT - MARGIN_TOP - MARGIN_BOTTOM)
+    }
+}
+
+/// Render an inline SVG of the water level over the next day or two, marking
+/// high and low tides, the current time and the hours of darkness at `coords`.
+///
+/// Times are labelled in `zone`. Returns `None` if the predictions don't
+/// cover enough of the chart to draw anything.
+pub fn tide_chart(
+    predictions: &[TidePrediction],
+    now: DateTime<FixedOffset>,
+    zone: &Zone,
+    coords: &Coordinates,
+) -> Option<String> {
+    let start = now - Duration::hours(HOURS_BEFORE);
+    let end = now + Duration::hours(HOURS_AFTER);
+    let samples =
+        compute::curve::sample_levels(predictions, start, end, Duration::minutes(SAMPLE_MINUTES));
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let levels = samples.iter().map(|s| s.tide.get::<meter>());
+    let (min, max) = match levels.minmax().into_option() {
+        Some((min, max)) if max > min => (min, max),
+        Some((level, _)) => (level - 0.5, level + 0.5),
+        None => return None,
+    };
+    let padding = (max - min) * 0.1;
+    let scale = Scale {
+        zone: *zone,
+        start,
+        end,
+        min: min - padding,
+        max: max + padding,
+    };
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        "<svg class='tide-chart' viewBox='0 0 {} {}' role='img' aria-label='Tide levels over the next {} hours'>",
+        WIDTH, HEIGHT, HOURS_AFTER
+    )
+    .unwrap();
+    draw_nights(&mut svg, &scale, coords);
+    draw_hours(&mut svg, &scale);
+    draw_levels(&mut svg, &scale);
+    draw_curve(&mut svg, &scale, &samples);
+    draw_extrema(&mut svg, &scale, predictions);
+    draw_now(&mut svg, &scale, now);
+    svg.push_str("</svg>");
+    Some(svg)
+}
+
+fn draw_nights(svg: &mut String, scale: &Scale, coords: &Coordinates) {
+    let first = scale.start.with_timezone(&Utc).date().naive_utc() - Duration::days(1);
+    let last = scale.end.with_timezone(&Utc).date().naive_utc() + Duration::days(1);
+    let mut days = vec![];
+    let mut date = first;
+    while date <= last {
+        match compute::sun::daylight(coords, date) {
+            Daylight::SunRisesAndSets { sunrise, sunset } => days.push((sunrise, sunset)),
+            Daylight::AlwaysDay => days.push((
+                Utc.from_utc_date(&date).and_hms(0, 0, 0),
+                Utc.from_utc_date(&date).and_hms(0, 0, 0) + Duration::days(1),
+            )),
+            Daylight::AlwaysNight => {}
+        }
+        date = date.succ();
+    }
+
+    let start = scale.start.with_timezone(&Utc);
+    let end = scale.end.with_timezone(&Utc);
+    let mut dark_from = start;
+    for (sunrise, sunset) in days.into_iter().sorted() {
+        if sunrise > dark_from {
+            draw_night(svg, scale, dark_from, sunrise.min(end));
+        }
+        dark_from = dark_from.max(sunset);
+    }
+    if dark_from < end {
+        draw_night(svg, scale, dark_from, end);
+    }
+}
+
+fn draw_night(svg: &mut String, scale: &Scale, from: DateTime<Utc>, to: DateTime<Utc>) {
+    if to <= from {
+        return;
+    }
+    let x = scale.x(&from);
+    write!(
+        svg,
+        "<rect class='night' x='{:.1}' y='{:.1}' width='{:.1}' height='{:.1}' />",
+        x,
+        MARGIN_TOP,
+        scale.x(&to) - x,
+        HEIGHT - MARGIN_TOP - MARGIN_BOTTOM
+    )
+    .unwrap();
+}
+
+fn draw_hours(svg: &mut String, scale: &Scale) {
+    let first_hour = scale.start.timestamp() / 3600 * 3600 + 3600;
+    let mut instant = Utc.timestamp(first_hour, 0);
+    while instant.timestamp() < scale.end.timestamp() {
+        // Step through UTC so that DST transitions don't skip or repeat ticks.
+        let time = scale.zone.localize(&instant);
+        instant = instant + Duration::hours(1);
+        if time.minute() == 0 && time.hour() % 6 == 0 {
+            let x = scale.x(&time);
+            let label = if time.hour() == 0 {
+                time.format("%a").to_string()
+            } else {
+                time.format("%-I %p").to_string()
+            };
+            write!(
+                svg,
+                "<line class='tick' x1='{x:.1}' y1='{}' x2='{x:.1}' y2='{}' />\
+                 <text class='tick-label' x='{x:.1}' y='{}' text-anchor='middle'>{}</text>",
+                MARGIN_TOP,
+                HEIGHT - MARGIN_BOTTOM,
+                HEIGHT - 6.0,
+                label,
+                x = x
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn draw_levels(svg: &mut String, scale: &Scale) {
+    for level in &[scale.min, scale.max] {
+        write!(
+            svg,
+            "<text class='level-label' x='{}' y='{:.1}' text-anchor='end'>{:.1}m</text>",
+            MARGIN_LEFT - 4.0,
+            scale.y(*level) + 4.0,
+            level
+        )
+        .unwrap();
+    }
+}
+
+fn draw_curve(svg: &mut String, scale: &Scale, samples: &[TidePrediction]) {
+    let points = samples
+        .iter()
+        .map(|s| {
+            format!(
+                "{:.1},{:.1}",
+                scale.x(&s.time),
+                scale.y(s.tide.get::<meter>())
+            )
+        })
+        .join(" L ");
+    let bottom = HEIGHT - MARGIN_BOTTOM;
+    let first_x = scale.x(&samples[0].time);
+    let last_x = scale.x(&samples[samples.len() - 1].time);
+    write!(
+        svg,
+        "<path class='tide-area' d='M {:.1},{} L {} L {:.1},{} Z' />\
+         <path class='tide-curve' d='M {}' />",
+        first_x, bottom, points, last_x, bottom, points
+    )
+    .unwrap();
+}
+
+fn draw_extrema(svg: &mut String, scale: &Scale, predictions: &[TidePrediction]) {
+    let sorted: Vec<_> = predictions
+        .iter()
+        .sorted_by_key(|p| p.time)
+        .cloned()
+        .collect();
+    for (i, prediction) in sorted.iter().enumerate() {
+        if prediction.time < scale.start || prediction.time > scale.end {
+            continue;
+        }
+        let is_high = compute::find::is_high_tide(&sorted, i);
+        let level = prediction.tide.get::<meter>();
+        let x = scale.x(&prediction.time);
+        let y = scale.y(level);
+        let local = scale.zone.localize(&prediction.time);
+        let (class, label_y) = if is_high {
+            ("high", y - 8.0)
+        } else {
+            ("low", y + 16.0)
+        };
+        write!(
+            svg,
+            "<circle class='{class}' cx='{x:.1}' cy='{y:.1}' r='4'>\
+             <title>{title} tide: {level:.2}m at {time}</title></circle>\
+             <text class='{class}-label' x='{x:.1}' y='{label_y:.1}' text-anchor='middle'>{level:.1}m {short}</text>",
+            class = class,
+            x = x,
+            y = y,
+            label_y = label_y,
+            title = if is_high { "High" } else { "Low" },
+            level = level,
+            time = local.format(crate::model::TIME_FORMAT),
+            short = local.format("%-I:%M %p"),
+        )
+        .unwrap();
+    }
+}
+
+fn draw_now(svg: &mut String, scale: &Scale, now: DateTime<FixedOffset>) {
+    let x = scale.x(&now);
+    write!(
+        svg,
+        "<line class='now' x1='{x:.1}' y1='{}' x2='{x:.1}' y2='{}' />\
+         <text class='now-label' x='{x:.1}' y='{}' text-anchor='middle'>Now</text>",
+        MARGIN_TOP,
+        HEIGHT - MARGIN_BOTTOM,
+        MARGIN_TOP - 6.0,
+        x = x
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use uom::si::f64::*;
+
+    fn predictions(now: DateTime<FixedOffset>) -> Vec<TidePrediction> {
+        (-1..10)
+            .map(|i| TidePrediction {
+                tide: Length::new::<meter>(if i % 2 == 0 { 4.0 } else { 1.0 }),
+                time: now + Duration::hours(6 * i),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_draws_the_curve_markers_and_now() {
+        let now = FixedOffset::west(7 * 3600)
+            .ymd(2019, 6, 21)
+            .and_hms(12, 0, 0);
+        let coords = Coordinates {
+            lat: 49.3299,
+            lon: -123.2650,
+        };
+        let zone = Zone::from_name("America/Vancouver").unwrap();
+        let svg = tide_chart(&predictions(now), now, &zone, &coords).unwrap();
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("class='tide-curve'"));
+        assert!(svg.contains("class='now'"));
+        assert_eq!(svg.matches("<circle class='high'").count(), 4);
+        assert_eq!(svg.matches("<circle class='low'").count(), 4);
+        // One stretch of darkness overnight and part of the next.
+        assert_eq!(svg.matches("class='night'").count(), 2);
+    }
+
+    #[test]
+    fn it_draws_nothing_without_predictions() {
+        let now = FixedOffset::west(0).ymd(2019, 6, 21).and_hms(12, 0, 0);
+        let coords = Coordinates { lat: 0.0, lon: 0.0 };
+        assert_eq!(
+            tide_chart(&[], now, &Zone::nautical(&coords), &coords),
+            None
+        );
+    }
+}
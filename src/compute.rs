@@ -1,2 +1,5 @@
+pub mod curve;
 pub mod find;
 pub mod gcd;
+pub mod spatial;
+pub mod sun;
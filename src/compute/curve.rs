@@ -0,0 +1,141 @@
+use crate::compute::find::{approximate_current_level, nearest_pair};
+use crate::model::{TidePrediction, TidePredictionPair};
+use chrono::prelude::*;
+use chrono::Duration;
+use itertools::Itertools;
+use uom::si::f64::*;
+
+/// The approximate water level at any time covered by `tides`.
+///
+/// Returns `None` when `time` isn't bracketed by two predictions.
+pub fn level_at(tides: &[TidePrediction], time: DateTime<FixedOffset>) -> Option<Length> {
+    nearest_pair(tides, time).map(|pair| approximate_current_level(&pair, &time))
+}
+
+/// Sample the water level every `step` from `start` to `end` (inclusive).
+///
+/// Every consecutive pair of extrema is joined with the same half cosine
+/// used by `approximate_current_level`. Its slope is zero at each extremum,
+/// so the joined curve is smooth across the transitions between pairs.
+/// Times that fall outside of the predictions are skipped rather than
+/// extrapolated, and the samples are returned in the offset of `start`.
+pub fn sample_levels(
+    tides: &[TidePrediction],
+    start: DateTime<FixedOffset>,
+    end: DateTime<FixedOffset>,
+    step: Duration,
+) -> Vec<TidePrediction> {
+    if step <= Duration::zero() {
+        return vec![];
+    }
+    let sorted: Vec<_> = tides.iter().sorted_by_key(|x| x.time).collect();
+    let mut samples = vec![];
+    let mut i = 0;
+    let mut time = start;
+
+    while time <= end {
+        while i + 2 < sorted.len() && sorted[i + 1].time < time {
+            i += 1;
+        }
+        if i + 1 < sorted.len() && sorted[i].time <= time && time <= sorted[i + 1].time {
+            let pair = TidePredictionPair {
+                prev: *sorted[i],
+                next: *sorted[i + 1],
+            };
+            let tide = if pair.prev.time == pair.next.time {
+                pair.prev.tide
+            } else {
+                approximate_current_level(&pair, &time)
+            };
+            samples.push(TidePrediction { tide, time });
+        }
+        time = time + step;
+    }
+    samples
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use uom::si::length::meter;
+
+    fn tides() -> Vec<TidePrediction> {
+        let pst = FixedOffset::west(8 * 3600);
+        vec![
+            (pst.ymd(2019, 5, 14).and_hms(6, 0, 0), 3.0),
+            (pst.ymd(2019, 5, 14).and_hms(0, 0, 0), 1.0),
+            (pst.ymd(2019, 5, 14).and_hms(12, 0, 0), 0.5),
+            (pst.ymd(2019, 5, 14).and_hms(18, 0, 0), 4.0),
+        ]
+        .into_iter()
+        .map(|(time, level)| TidePrediction {
+            tide: Length::new::<meter>(level),
+            time,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn it_passes_through_every_extremum() {
+        let tides = tides();
+        let start = tides[1].time;
+        let end = tides[3].time;
+        let samples = sample_levels(&tides, start, end, Duration::hours(6));
+
+        let levels: Vec<f64> = samples.iter().map(|s| s.tide.get::<meter>()).collect();
+        assert_eq!(levels.len(), 4);
+        for (level, expected) in levels.iter().zip(&[1.0, 3.0, 0.5, 4.0]) {
+            assert!((level - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn it_samples_at_the_requested_interval() {
+        let tides = tides();
+        let start = tides[1].time;
+        let samples = sample_levels(&tides, start, tides[3].time, Duration::minutes(6));
+
+        assert_eq!(samples.len(), 18 * 10 + 1);
+        assert!(samples
+            .windows(2)
+            .all(|w| w[1].time - w[0].time == Duration::minutes(6)));
+    }
+
+    #[test]
+    fn it_is_smooth_across_extrema() {
+        let tides = tides();
+        let high = tides[0].time;
+        let samples = sample_levels(
+            &tides,
+            high - Duration::minutes(1),
+            high + Duration::minutes(1),
+            Duration::minutes(1),
+        );
+        let levels: Vec<f64> = samples.iter().map(|s| s.tide.get::<meter>()).collect();
+
+        // Both neighbours sit just below the high tide, so there's no kink.
+        assert!(levels[0] < levels[1] && levels[2] < levels[1]);
+        assert!((levels[1] - levels[0]).abs() < 1e-3);
+        assert!((levels[1] - levels[2]).abs() < 1e-3);
+    }
+
+    #[test]
+    fn it_skips_times_outside_of_the_predictions() {
+        let tides = tides();
+        let start = tides[1].time - Duration::hours(1);
+        let end = tides[3].time + Duration::hours(1);
+        let samples = sample_levels(&tides, start, end, Duration::hours(1));
+
+        assert_eq!(samples.first().unwrap().time, tides[1].time);
+        assert_eq!(samples.last().unwrap().time, tides[3].time);
+        assert_eq!(level_at(&tides, end), None);
+    }
+
+    #[test]
+    fn it_finds_the_level_at_a_given_time() {
+        let tides = tides();
+        let level = level_at(&tides, tides[0].time + Duration::hours(3)).unwrap();
+
+        assert!((level.get::<meter>() - 1.75).abs() < 1e-9);
+    }
+}
@@ -19,6 +19,33 @@ pub fn nearest_pair(
     }
 }
 
+/// Like `nearest_pair`, but for predictions that are already sorted by time,
+/// which allows a binary search instead of sorting.
+pub fn bracketing_pair(
+    sorted: &[TidePrediction],
+    time: DateTime<FixedOffset>,
+) -> Option<TidePredictionPair> {
+    let after = sorted.partition_point(|x| x.is_before(time));
+    match (after.checked_sub(1), sorted.get(after)) {
+        (Some(before), Some(next)) => Some(TidePredictionPair {
+            next: *next,
+            prev: sorted[before],
+        }),
+        _ => None,
+    }
+}
+
+/// Whether the prediction at `index` is a high tide, judged against its
+/// neighbours. `tides` must be sorted by time.
+pub fn is_high_tide(tides: &[TidePrediction], index: usize) -> bool {
+    let neighbour = if index > 0 {
+        tides.get(index - 1)
+    } else {
+        tides.get(index + 1)
+    };
+    neighbour.map_or(true, |n| tides[index].tide > n.tide)
+}
+
 /// Given two predictions and a time between them, use a sinusoidal
 /// function to approximate the current tide level.
 ///
@@ -85,6 +112,41 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_agrees_with_nearest_pair_on_sorted_input() {
+        let pst = FixedOffset::west(8 * 3600);
+        let tides: Vec<_> = (0..6)
+            .map(|i| TidePrediction {
+                tide: Length::new::<meter>(f64::from(i % 2)),
+                time: pst.ymd(2019, 5, 14).and_hms(i * 4, 0, 0),
+            })
+            .collect();
+
+        for hour in 0..23 {
+            let time = pst.ymd(2019, 5, 14).and_hms(hour, 30, 0);
+            assert_eq!(bracketing_pair(&tides, time), nearest_pair(&tides, time));
+        }
+        let exact = tides[2].time;
+        assert_eq!(bracketing_pair(&tides, exact), nearest_pair(&tides, exact));
+        assert_eq!(bracketing_pair(&[], exact), None);
+    }
+
+    #[test]
+    fn it_tells_highs_from_lows() {
+        let pst = FixedOffset::west(8 * 3600);
+        let tides: Vec<_> = [1.0, 3.0, 0.5, 2.0]
+            .iter()
+            .enumerate()
+            .map(|(i, level)| TidePrediction {
+                tide: Length::new::<meter>(*level),
+                time: pst.ymd(2019, 5, 14).and_hms(i as u32 * 6, 0, 0),
+            })
+            .collect();
+
+        let highs: Vec<_> = (0..tides.len()).map(|i| is_high_tide(&tides, i)).collect();
+        assert_eq!(highs, vec![false, true, false, true]);
+    }
+
     #[test]
     fn it_can_interpolate_times() {
         let pst = FixedOffset::west(8 * 3600);
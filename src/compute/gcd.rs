@@ -12,7 +12,7 @@ pub fn great_circle_distance(p1: &Coordinates, p2: &Coordinates) -> Length {
     central * earth_radius()
 }
 
-fn earth_radius() -> Length {
+pub fn earth_radius() -> Length {
     Length::new::<meter>(6_371_000.0)
 }
 
@@ -20,14 +20,14 @@ fn earth_radius() -> Length {
 /// point errors.
 fn check_acos_domain(x: f64) -> f64 {
     const ACCEPTABLE_DELTA: f64 = 1e-11;
-    if -1.0 <= x && x <= 1.0 {
+    if (-1.0..=1.0).contains(&x) {
         return x;
     }
     if x < -1.0 && (-1.0 - ACCEPTABLE_DELTA) < x {
-        return -1.0 + std::f64::EPSILON;
+        return -1.0 + f64::EPSILON;
     }
     if x > 1.0 && (1.0 + ACCEPTABLE_DELTA) > x {
-        return 1.0 - std::f64::EPSILON;
+        return 1.0 - f64::EPSILON;
     }
     panic!("f64 outside of arcos range ({})", x);
 }
@@ -0,0 +1,296 @@
+use crate::model::Coordinates;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+type Point = [f64; 3];
+
+/// Position of a coordinate on the unit sphere.
+///
+/// Straight line (chord) distance between two of these grows with the great
+/// circle distance between the coordinates, so ranking by one ranks by the
+/// other without any trigonometry at query time.
+pub fn unit_vector(coords: &Coordinates) -> Point {
+    let (lat, lon) = coords.to_radians();
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+fn squared_distance(a: &Point, b: &Point) -> f64 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum()
+}
+
+/// A k-d tree over positions on the unit sphere, answering nearest
+/// neighbour queries by index into the coordinates it was built from.
+///
+/// The tree is implicit: `nodes` is ordered so that the median of every
+/// range is the root of that range's subtree.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    nodes: Vec<(Point, usize)>,
+}
+
+impl SpatialIndex {
+    pub fn new(coordinates: &[Coordinates]) -> Self {
+        let mut nodes: Vec<_> = coordinates
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (unit_vector(c), i))
+            .collect();
+        build(&mut nodes, 0);
+        SpatialIndex { nodes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Index of the coordinate closest to `target`.
+    pub fn nearest(&self, target: &Coordinates) -> Option<usize> {
+        self.k_nearest(target, 1).first().cloned()
+    }
+
+    /// Indices of the `k` coordinates closest to `target`, nearest first.
+    pub fn k_nearest(&self, target: &Coordinates, k: usize) -> Vec<usize> {
+        if k == 0 {
+            return vec![];
+        }
+        let target = unit_vector(target);
+        let mut best = BinaryHeap::with_capacity(k + 1);
+        self.search(&target, 0, self.nodes.len(), 0, k, &mut best);
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|c| c.index)
+            .collect()
+    }
+
+    /// Indices of every coordinate within `angle` radians of `target`
+    /// (measured along a great circle), in no particular order.
+    pub fn within(&self, target: &Coordinates, angle: f64) -> Vec<usize> {
+        if angle >= std::f64::consts::PI {
+            return self.nodes.iter().map(|(_, i)| *i).collect();
+        }
+        let chord = 2.0 * (angle.max(0.0) / 2.0).sin();
+        let mut found = vec![];
+        self.search_within(
+            &unit_vector(target),
+            chord * chord,
+            0,
+            self.nodes.len(),
+            0,
+            &mut found,
+        );
+        found
+    }
+
+    fn search_within(
+        &self,
+        target: &Point,
+        max_distance: f64,
+        lo: usize,
+        hi: usize,
+        depth: usize,
+        found: &mut Vec<usize>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let (point, index) = &self.nodes[mid];
+        if squared_distance(target, point) <= max_distance {
+            found.push(*index);
+        }
+        let axis = depth % 3;
+        let delta = target[axis] - point[axis];
+        if delta < 0.0 || delta * delta <= max_distance {
+            self.search_within(target, max_distance, lo, mid, depth + 1, found);
+        }
+        if delta >= 0.0 || delta * delta <= max_distance {
+            self.search_within(target, max_distance, mid + 1, hi, depth + 1, found);
+        }
+    }
+
+    fn search(
+        &self,
+        target: &Point,
+        lo: usize,
+        hi: usize,
+        depth: usize,
+        k: usize,
+        best: &mut BinaryHeap<Candidate>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let (point, index) = &self.nodes[mid];
+        best.push(Candidate {
+            distance: squared_distance(target, point),
+            index: *index,
+        });
+        if best.len() > k {
+            best.pop();
+        }
+
+        let axis = depth % 3;
+        let delta = target[axis] - point[axis];
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.search(target, near.0, near.1, depth + 1, k, best);
+        let worst = best.peek().map_or(f64::INFINITY, |c| c.distance);
+        if best.len() < k || delta * delta < worst {
+            self.search(target, far.0, far.1, depth + 1, k, best);
+        }
+    }
+}
+
+fn build(nodes: &mut [(Point, usize)], depth: usize) {
+    if nodes.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = nodes.len() / 2;
+    nodes.select_nth_unstable_by(mid, |a, b| {
+        a.0[axis].partial_cmp(&b.0[axis]).unwrap_or(Ordering::Equal)
+    });
+    let (left, right) = nodes.split_at_mut(mid);
+    build(left, depth + 1);
+    build(&mut right[1..], depth + 1);
+}
+
+/// A max-heap entry, so the worst of the current best candidates is on top.
+#[derive(Debug, PartialEq)]
+struct Candidate {
+    distance: f64,
+    index: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .partial_cmp(&other.distance)
+            .unwrap_or(Ordering::Equal)
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compute::gcd::great_circle_distance;
+
+    /// Deterministic scattering of points over the whole globe.
+    fn points(n: usize, seed: u64) -> Vec<Coordinates> {
+        let mut state = seed;
+        let mut next = move || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        (0..n)
+            .map(|_| Coordinates {
+                lat: next() * 180.0 - 90.0,
+                lon: next() * 360.0 - 180.0,
+            })
+            .collect()
+    }
+
+    fn brute_force(points: &[Coordinates], target: &Coordinates, k: usize) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..points.len()).collect();
+        indices.sort_by(|a, b| {
+            let da = great_circle_distance(&points[*a], target);
+            let db = great_circle_distance(&points[*b], target);
+            da.partial_cmp(&db).unwrap()
+        });
+        indices.truncate(k);
+        indices
+    }
+
+    #[test]
+    fn it_finds_the_same_nearest_point_as_a_linear_scan() {
+        let stations = points(500, 1);
+        let index = SpatialIndex::new(&stations);
+        for target in points(200, 2) {
+            assert_eq!(
+                index.nearest(&target),
+                brute_force(&stations, &target, 1).first().cloned()
+            );
+        }
+    }
+
+    #[test]
+    fn it_finds_the_k_nearest_points_in_order() {
+        let stations = points(300, 3);
+        let index = SpatialIndex::new(&stations);
+        for target in points(50, 4) {
+            assert_eq!(
+                index.k_nearest(&target, 5),
+                brute_force(&stations, &target, 5)
+            );
+        }
+    }
+
+    #[test]
+    fn it_finds_every_point_within_a_radius() {
+        use crate::compute::gcd::earth_radius;
+        use uom::si::f64::*;
+        use uom::si::length::kilometer;
+
+        let stations = points(400, 5);
+        let index = SpatialIndex::new(&stations);
+        let radius = Length::new::<kilometer>(2000.0);
+        let angle = (radius / earth_radius()).value;
+        for target in points(50, 6) {
+            let mut found = index.within(&target, angle);
+            found.sort();
+            let expected: Vec<usize> = (0..stations.len())
+                .filter(|i| great_circle_distance(&stations[*i], &target) <= radius)
+                .collect();
+            assert_eq!(found, expected);
+        }
+        assert_eq!(index.within(&stations[0], 4.0).len(), stations.len());
+    }
+
+    #[test]
+    fn it_handles_the_antimeridian() {
+        let stations = vec![
+            Coordinates {
+                lat: 0.0,
+                lon: 179.9,
+            },
+            Coordinates {
+                lat: 0.0,
+                lon: 170.0,
+            },
+        ];
+        let index = SpatialIndex::new(&stations);
+        let target = Coordinates {
+            lat: 0.0,
+            lon: -179.9,
+        };
+        assert_eq!(index.nearest(&target), Some(0));
+    }
+
+    #[test]
+    fn it_handles_small_and_empty_indexes() {
+        let target = Coordinates { lat: 0.0, lon: 0.0 };
+        assert_eq!(SpatialIndex::new(&[]).nearest(&target), None);
+        let one = SpatialIndex::new(&[target]);
+        assert_eq!(one.k_nearest(&target, 3), vec![0]);
+        assert_eq!(one.k_nearest(&target, 0), Vec::<usize>::new());
+    }
+}
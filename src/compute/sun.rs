@@ -0,0 +1,106 @@
+use crate::model::Coordinates;
+use chrono::prelude::*;
+
+/// Julian date of the unix epoch.
+const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+/// Julian date of the J2000 epoch.
+const J2000_JD: f64 = 2_451_545.0;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Daylight {
+    SunRisesAndSets {
+        sunrise: DateTime<Utc>,
+        sunset: DateTime<Utc>,
+    },
+    /// The sun doesn't set (polar day).
+    AlwaysDay,
+    /// The sun doesn't rise (polar night).
+    AlwaysNight,
+}
+
+/// Approximate sunrise and sunset around solar noon of `date` at `coords`.
+///
+/// Uses the sunrise equation from
+/// https://en.wikipedia.org/wiki/Sunrise_equation, which is good to within
+/// a minute or two away from the poles. That's plenty for shading a chart.
+pub fn daylight(coords: &Coordinates, date: NaiveDate) -> Daylight {
+    let midnight = date.and_hms(0, 0, 0).timestamp() as f64;
+    let n = (midnight / 86_400.0 + UNIX_EPOCH_JD - J2000_JD + 0.0008).ceil();
+    let mean_solar_time = n - coords.lon / 360.0;
+
+    let anomaly = (357.5291 + 0.985_600_28 * mean_solar_time).rem_euclid(360.0);
+    let m = anomaly.to_radians();
+    let center = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit =
+        J2000_JD + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * 23.4397_f64.to_radians().sin()).asin();
+    let lat = coords.lat.to_radians();
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - lat.sin() * declination.sin())
+        / (lat.cos() * declination.cos());
+
+    if cos_hour_angle > 1.0 {
+        return Daylight::AlwaysNight;
+    }
+    if cos_hour_angle < -1.0 {
+        return Daylight::AlwaysDay;
+    }
+    let half_day = cos_hour_angle.acos().to_degrees() / 360.0;
+    Daylight::SunRisesAndSets {
+        sunrise: from_julian(transit - half_day),
+        sunset: from_julian(transit + half_day),
+    }
+}
+
+fn from_julian(jd: f64) -> DateTime<Utc> {
+    let seconds = ((jd - UNIX_EPOCH_JD) * 86_400.0).round() as i64;
+    Utc.timestamp(seconds, 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    fn assert_close(actual: DateTime<Utc>, expected: DateTime<Utc>) {
+        let diff = (actual - expected).num_seconds().abs();
+        assert!(diff < 5 * 60, "{} is not close to {}", actual, expected);
+    }
+
+    #[test]
+    fn it_finds_sunrise_and_sunset_in_vancouver() {
+        let vancouver = Coordinates {
+            lat: 49.2827,
+            lon: -123.1207,
+        };
+        let summer = daylight(&vancouver, NaiveDate::from_ymd(2019, 6, 21));
+        match summer {
+            Daylight::SunRisesAndSets { sunrise, sunset } => {
+                // 5:07am and 9:21pm PDT.
+                assert_close(sunrise, Utc.ymd(2019, 6, 21).and_hms(12, 7, 0));
+                assert_close(sunset, Utc.ymd(2019, 6, 22).and_hms(4, 21, 0));
+                assert!(sunset - sunrise > Duration::hours(16));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_knows_about_polar_day_and_night() {
+        let svalbard = Coordinates {
+            lat: 78.2232,
+            lon: 15.6267,
+        };
+        assert_eq!(
+            daylight(&svalbard, NaiveDate::from_ymd(2019, 6, 21)),
+            Daylight::AlwaysDay
+        );
+        assert_eq!(
+            daylight(&svalbard, NaiveDate::from_ymd(2019, 12, 21)),
+            Daylight::AlwaysNight
+        );
+    }
+}
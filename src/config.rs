@@ -0,0 +1,535 @@
+//! Settings for the server and command line tools.
+//!
+//! Each setting comes from, in increasing priority: its default, a TOML
+//! file, an environment variable and a command line flag. The file is
+//! named by `--config` or `WTIIRN_CONFIG`, and otherwise `wtiirn.toml` is
+//! read if it exists. For example:
+//!
+//! ```toml
+//! host = "0.0.0.0"
+//! port = 8080
+//! log_level = "info"
+//! log_format = "json"
+//!
+//! [data]
+//! stations = "/srv/wtiirn/stations"
+//! predictions = "/srv/wtiirn/predictions"
+//!
+//! [defaults]
+//! lat = 49.3299
+//! lon = -123.265
+//! time_zone = "America/Vancouver"
+//!
+//! [readiness]
+//! min_days_ahead = 14
+//! min_fraction = 0.5
+//!
+//! [https]
+//! canonical_host = "whattideisitrightnow.com"
+//! hsts_max_age_secs = 31536000
+//! ```
+
+use serde::Deserialize;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::health::Readiness;
+use crate::https::{self, Https};
+use crate::logging::LogFormat;
+use crate::model::Coordinates;
+use crate::pages;
+use crate::server;
+use crate::stations::DataPaths;
+use crate::zone::Zone;
+
+static DEFAULT_CONFIG_FILE: &str = "wtiirn.toml";
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub data: DataPaths,
+    pub defaults: DefaultsConfig,
+    pub https: HttpsConfig,
+    pub readiness: Readiness,
+    /// An `env_logger` filter, e.g. `info` or `wtiirn=debug`.
+    pub log_level: String,
+    pub log_format: LogFormat,
+    /// Refuse to start, or to reload, if any data file is malformed.
+    pub strict: bool,
+    /// How often to check the data directories for changes. `0` turns
+    /// reloading off.
+    pub reload_secs: u64,
+    /// How many requests are handled at once.
+    pub workers: usize,
+    /// How long a client has to send its request, and to take the response.
+    pub request_timeout_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let location = pages::Defaults::default().location;
+        Config {
+            host: "127.0.0.1".to_string(),
+            port: 7878,
+            data: DataPaths::default(),
+            defaults: DefaultsConfig {
+                lat: location.lat,
+                lon: location.lon,
+                time_zone: None,
+            },
+            https: HttpsConfig {
+                redirect: true,
+                canonical_host: https::CANONICAL_HOST.to_string(),
+                hsts_max_age_secs: 0,
+            },
+            readiness: Readiness::default(),
+            log_level: "info".to_string(),
+            log_format: LogFormat::Text,
+            strict: false,
+            reload_secs: 60,
+            workers: 8,
+            request_timeout_secs: 30,
+        }
+    }
+}
+
+/// What pages show when a request doesn't say; see `pages::Defaults`.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultsConfig {
+    pub lat: f64,
+    pub lon: f64,
+    /// IANA name of the zone to show times in.
+    pub time_zone: Option<String>,
+}
+
+impl Default for DefaultsConfig {
+    fn default() -> Self {
+        Config::default().defaults
+    }
+}
+
+/// Sending plain HTTP visitors to HTTPS; see `https::Https`.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpsConfig {
+    /// Turn this off when running locally behind a proxy without TLS.
+    pub redirect: bool,
+    /// The only host visitors are redirected to.
+    pub canonical_host: String,
+    pub hsts_max_age_secs: u64,
+}
+
+impl Default for HttpsConfig {
+    fn default() -> Self {
+        Config::default().https
+    }
+}
+
+/// Every setting that can be overridden, with its environment variable
+/// and command line flag.
+static OVERRIDES: &[(&str, &str, &str)] = &[
+    ("host", "WTIIRN_HOST", "--host"),
+    ("port", "PORT", "--port"),
+    ("data.stations", "WTIIRN_STATIONS_DIR", "--stations-dir"),
+    (
+        "data.predictions",
+        "WTIIRN_PREDICTIONS_DIR",
+        "--predictions-dir",
+    ),
+    ("data.noaa", "WTIIRN_NOAA_DIR", "--noaa-dir"),
+    ("defaults.lat", "WTIIRN_DEFAULT_LAT", "--default-lat"),
+    ("defaults.lon", "WTIIRN_DEFAULT_LON", "--default-lon"),
+    ("defaults.time_zone", "WTIIRN_DEFAULT_TZ", "--default-tz"),
+    (
+        "https.redirect",
+        "WTIIRN_HTTPS_REDIRECT",
+        "--https-redirect",
+    ),
+    (
+        "https.canonical_host",
+        "WTIIRN_CANONICAL_HOST",
+        "--canonical-host",
+    ),
+    (
+        "https.hsts_max_age_secs",
+        "WTIIRN_HSTS_MAX_AGE",
+        "--hsts-max-age",
+    ),
+    (
+        "readiness.min_days_ahead",
+        "WTIIRN_READY_DAYS_AHEAD",
+        "--ready-days-ahead",
+    ),
+    (
+        "readiness.min_fraction",
+        "WTIIRN_READY_MIN_FRACTION",
+        "--ready-min-fraction",
+    ),
+    ("log_level", "WTIIRN_LOG", "--log-level"),
+    ("log_format", "WTIIRN_LOG_FORMAT", "--log-format"),
+    ("strict", "WTIIRN_STRICT", "--strict"),
+    ("reload_secs", "WTIIRN_RELOAD_SECS", "--reload-secs"),
+    ("workers", "WTIIRN_WORKERS", "--workers"),
+    (
+        "request_timeout_secs",
+        "WTIIRN_REQUEST_TIMEOUT_SECS",
+        "--request-timeout-secs",
+    ),
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConfigError {
+    Read { path: PathBuf, reason: String },
+    Parse { path: PathBuf, reason: String },
+    InvalidValue { setting: String, value: String },
+    UnknownFlag(String),
+    MissingValue(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, reason } => {
+                write!(f, "couldn't read {}: {}", path.display(), reason)
+            }
+            ConfigError::Parse { path, reason } => {
+                write!(f, "couldn't parse {}: {}", path.display(), reason)
+            }
+            ConfigError::InvalidValue { setting, value } => {
+                write!(f, "invalid value {:?} for {}", value, setting)
+            }
+            ConfigError::UnknownFlag(flag) => write!(f, "unknown option {}", flag),
+            ConfigError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl Config {
+    /// Read the configuration for this process from its file, environment
+    /// and command line.
+    pub fn from_environment() -> Result<Self, ConfigError> {
+        let args: Vec<String> = env::args().skip(1).collect();
+        Config::load(&args, |name| env::var(name).ok())
+    }
+
+    /// Read the configuration given command line arguments, without the
+    /// program name, and a way to look up environment variables.
+    pub fn load<F>(args: &[String], var: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let flags = parse_flags(args)?;
+        let named_file = flags
+            .iter()
+            .find(|(setting, _)| *setting == "config")
+            .map(|(_, value)| PathBuf::from(value))
+            .or_else(|| var("WTIIRN_CONFIG").map(PathBuf::from));
+
+        let mut config = match named_file {
+            Some(path) => Config::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+        for (setting, name, _) in OVERRIDES {
+            if let Some(value) = var(name) {
+                config.set(setting, &value)?;
+            }
+        }
+        for (setting, value) in flags.iter().filter(|(s, _)| *s != "config") {
+            config.set(setting, value)?;
+        }
+        config.page_defaults()?;
+        config.https()?;
+        if !(0.0..=1.0).contains(&config.readiness.min_fraction) {
+            return Err(ConfigError::InvalidValue {
+                setting: "readiness.min_fraction".to_string(),
+                value: config.readiness.min_fraction.to_string(),
+            });
+        }
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let src = fs::read_to_string(path).map_err(|e| ConfigError::Read {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        toml::from_str(&src).map_err(|e| ConfigError::Parse {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })
+    }
+
+    /// The address to listen on.
+    pub fn bind_address(&self) -> (String, String) {
+        (self.host.clone(), self.port.to_string())
+    }
+
+    /// How the server should handle connections. Each worker can have a few
+    /// connections waiting for it before new ones are turned away.
+    pub fn server_options(&self) -> server::Options {
+        server::Options {
+            workers: self.workers,
+            queue: self.workers * 8,
+            timeout: Duration::from_secs(self.request_timeout_secs.max(1)),
+            ..server::Options::default()
+        }
+    }
+
+    /// How to enforce HTTPS, or `None` if it's turned off, checking the
+    /// canonical host is a valid host name.
+    pub fn https(&self) -> Result<Option<Https>, ConfigError> {
+        if !self.https.redirect {
+            return Ok(None);
+        }
+        let host = &self.https.canonical_host;
+        let canonical_host = host.parse().map_err(|_| ConfigError::InvalidValue {
+            setting: "https.canonical_host".to_string(),
+            value: host.clone(),
+        })?;
+        Ok(Some(Https {
+            canonical_host,
+            hsts_max_age_secs: self.https.hsts_max_age_secs,
+        }))
+    }
+
+    /// The fallbacks pages use, checking the default time zone exists.
+    pub fn page_defaults(&self) -> Result<pages::Defaults, ConfigError> {
+        let zone = match &self.defaults.time_zone {
+            Some(name) => Some(
+                Zone::from_name(name).ok_or_else(|| ConfigError::InvalidValue {
+                    setting: "defaults.time_zone".to_string(),
+                    value: name.clone(),
+                })?,
+            ),
+            None => None,
+        };
+        Ok(pages::Defaults {
+            location: Coordinates {
+                lat: self.defaults.lat,
+                lon: self.defaults.lon,
+            },
+            zone,
+        })
+    }
+
+    fn set(&mut self, setting: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::InvalidValue {
+            setting: setting.to_string(),
+            value: value.to_string(),
+        };
+        match setting {
+            "host" => self.host = value.to_string(),
+            "port" => self.port = value.parse().map_err(|_| invalid())?,
+            "data.stations" => self.data.stations = PathBuf::from(value),
+            "data.predictions" => self.data.predictions = PathBuf::from(value),
+            "data.noaa" => self.data.noaa = PathBuf::from(value),
+            "defaults.lat" => self.defaults.lat = value.parse().map_err(|_| invalid())?,
+            "defaults.lon" => self.defaults.lon = value.parse().map_err(|_| invalid())?,
+            "defaults.time_zone" => {
+                self.defaults.time_zone = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "https.redirect" => self.https.redirect = parse_bool(value).ok_or_else(invalid)?,
+            "https.canonical_host" => self.https.canonical_host = value.to_string(),
+            "https.hsts_max_age_secs" => {
+                self.https.hsts_max_age_secs = value.parse().map_err(|_| invalid())?
+            }
+            "readiness.min_days_ahead" => {
+                self.readiness.min_days_ahead = value.parse().map_err(|_| invalid())?
+            }
+            "readiness.min_fraction" => {
+                self.readiness.min_fraction = value.parse().map_err(|_| invalid())?
+            }
+            "log_level" => self.log_level = value.to_string(),
+            "log_format" => self.log_format = value.parse().map_err(|_| invalid())?,
+            "strict" => self.strict = parse_bool(value).ok_or_else(invalid)?,
+            "reload_secs" => self.reload_secs = value.parse().map_err(|_| invalid())?,
+            "workers" => {
+                self.workers = value
+                    .parse()
+                    .ok()
+                    .filter(|workers| *workers > 0)
+                    .ok_or_else(invalid)?
+            }
+            "request_timeout_secs" => {
+                self.request_timeout_secs = value
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(invalid)?
+            }
+            _ => return Err(invalid()),
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "" | "0" | "false" | "no" => Some(false),
+        "1" | "true" | "yes" => Some(true),
+        _ => None,
+    }
+}
+
+/// Pair each flag's setting with its value. `--strict` doesn't need one.
+fn parse_flags(args: &[String]) -> Result<Vec<(&'static str, String)>, ConfigError> {
+    let mut flags = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let setting = match arg.as_str() {
+            "--config" => "config",
+            "--strict" => {
+                flags.push(("strict", "true".to_string()));
+                continue;
+            }
+            flag => OVERRIDES
+                .iter()
+                .find(|(_, _, f)| *f == flag)
+                .map(|(setting, _, _)| *setting)
+                .ok_or_else(|| ConfigError::UnknownFlag(flag.to_string()))?,
+        };
+        let value = args
+            .next()
+            .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+        flags.push((setting, value.clone()));
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn it_should_default_to_the_old_behaviour() {
+        let config = Config::load(&[], |_| None).unwrap();
+
+        assert_eq!(config, Config::default());
+        assert_eq!(config.page_defaults().unwrap(), pages::Defaults::default());
+    }
+
+    #[test]
+    fn it_should_read_a_toml_file() {
+        let path = std::env::temp_dir().join(format!("wtiirn-{}.toml", uuid::Uuid::new_v4()));
+        fs::write(
+            &path,
+            "port = 8080\n\
+             [data]\n\
+             stations = \"/srv/stations\"\n\
+             [defaults]\n\
+             lat = 1.5\n\
+             lon = 2.5\n\
+             time_zone = \"America/Vancouver\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&args(&["--config", path.to_str().unwrap()]), |_| None);
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.host, "127.0.0.1");
+        assert_eq!(config.data.stations, PathBuf::from("/srv/stations"));
+        assert_eq!(config.data.predictions, PathBuf::from("data/predictions"));
+        assert_eq!(
+            config.page_defaults().unwrap(),
+            pages::Defaults {
+                location: Coordinates { lat: 1.5, lon: 2.5 },
+                zone: Zone::from_name("America/Vancouver"),
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_prefer_flags_to_environment_variables() {
+        let env: HashMap<&str, &str> = [("PORT", "9000"), ("WTIIRN_HOST", "0.0.0.0")]
+            .iter()
+            .cloned()
+            .collect();
+        let var = |name: &str| env.get(name).map(|v| v.to_string());
+
+        let config = Config::load(&args(&["--port", "9001", "--strict"]), var).unwrap();
+
+        assert_eq!(
+            config.bind_address(),
+            ("0.0.0.0".to_string(), "9001".to_string())
+        );
+        assert!(config.strict);
+    }
+
+    #[test]
+    fn it_should_let_https_redirects_be_turned_off() {
+        let on = Config::load(&args(&["--hsts-max-age", "60"]), |_| None).unwrap();
+        let off = Config::load(&[], |name| match name {
+            "WTIIRN_HTTPS_REDIRECT" => Some("false".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        assert_eq!(
+            on.https().unwrap(),
+            Some(Https {
+                canonical_host: "whattideisitrightnow.com".parse().unwrap(),
+                hsts_max_age_secs: 60,
+            })
+        );
+        assert_eq!(off.https().unwrap(), None);
+    }
+
+    #[test]
+    fn it_should_reject_bad_settings() {
+        assert_eq!(
+            Config::load(&args(&["--port", "lots"]), |_| None),
+            Err(ConfigError::InvalidValue {
+                setting: "port".to_string(),
+                value: "lots".to_string()
+            })
+        );
+        assert_eq!(
+            Config::load(&args(&["--default-tz", "Moon/Base"]), |_| None),
+            Err(ConfigError::InvalidValue {
+                setting: "defaults.time_zone".to_string(),
+                value: "Moon/Base".to_string()
+            })
+        );
+        assert_eq!(
+            Config::load(&args(&["--canonical-host", "a.com/path"]), |_| None),
+            Err(ConfigError::InvalidValue {
+                setting: "https.canonical_host".to_string(),
+                value: "a.com/path".to_string()
+            })
+        );
+        assert_eq!(
+            Config::load(&args(&["--ready-min-fraction", "1.5"]), |_| None),
+            Err(ConfigError::InvalidValue {
+                setting: "readiness.min_fraction".to_string(),
+                value: "1.5".to_string()
+            })
+        );
+        assert_eq!(
+            Config::load(&args(&["--verbose"]), |_| None),
+            Err(ConfigError::UnknownFlag("--verbose".to_string()))
+        );
+        assert_eq!(
+            Config::load(&args(&["--host"]), |_| None),
+            Err(ConfigError::MissingValue("--host".to_string()))
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! `/healthz` and `/readyz`, for the platform running the server.
+//!
+//! `/healthz` only says the server is answering requests. `/readyz` also
+//! checks the catalogue has stations with predictions far enough ahead, so
+//! the site can be taken out of rotation before its tide data runs out.
+
+use chrono::prelude::*;
+use chrono::Duration;
+use http::header;
+use http::{Response, StatusCode};
+use serde::Deserialize;
+use std::fmt;
+
+use crate::reload::SharedCatalogue;
+use crate::router::{HandlerResult, Router};
+use crate::stations::StationCatalogue;
+
+/// How much of the catalogue must have predictions for how far ahead.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Readiness {
+    pub min_days_ahead: u32,
+    /// The share of stations, from 0 to 1, that need predictions until at
+    /// least `min_days_ahead` days from now.
+    pub min_fraction: f64,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Readiness {
+            min_days_ahead: 7,
+            min_fraction: 0.5,
+        }
+    }
+}
+
+/// Why the catalogue isn't ready to serve, or how it passed.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Check {
+    Ready {
+        covered: usize,
+        stations: usize,
+        until: DateTime<FixedOffset>,
+    },
+    NoStations,
+    ExpiringSoon {
+        covered: usize,
+        stations: usize,
+        until: DateTime<FixedOffset>,
+    },
+}
+
+impl Check {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Check::Ready { .. })
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Check::Ready {
+                covered,
+                stations,
+                until,
+            } => write!(
+                f,
+                "ready: {} of {} stations have predictions until {}",
+                covered,
+                stations,
+                until.to_rfc3339()
+            ),
+            Check::NoStations => write!(f, "not ready: no stations are loaded"),
+            Check::ExpiringSoon {
+                covered,
+                stations,
+                until,
+            } => write!(
+                f,
+                "not ready: only {} of {} stations have predictions until {}",
+                covered,
+                stations,
+                until.to_rfc3339()
+            ),
+        }
+    }
+}
+
+impl Readiness {
+    pub fn check(&self, catalogue: &StationCatalogue, now: DateTime<FixedOffset>) -> Check {
+        let stations = catalogue.summary().stations;
+        if stations == 0 {
+            return Check::NoStations;
+        }
+        let until = now + Duration::days(i64::from(self.min_days_ahead));
+        let covered = catalogue.stations_covered_until(until);
+        if (covered as f64) < self.min_fraction * stations as f64 {
+            Check::ExpiringSoon {
+                covered,
+                stations,
+                until,
+            }
+        } else {
+            Check::Ready {
+                covered,
+                stations,
+                until,
+            }
+        }
+    }
+}
+
+/// Add `/healthz` and `/readyz` to `router`.
+pub fn routes(router: Router, catalogue: SharedCatalogue, readiness: Readiness) -> Router {
+    router
+        .get("/healthz", |_, _| text(StatusCode::OK, "ok".to_string()))
+        .get("/readyz", move |_, _| {
+            let now = Utc::now().with_timezone(&FixedOffset::east(0));
+            let check = readiness.check(&catalogue.get(), now);
+            let status = if check.is_ready() {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            text(status, check.to_string())
+        })
+}
+
+fn text(status: StatusCode, body: String) -> HandlerResult {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(body.into_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{Coordinates, TidePrediction};
+    use crate::stations::{PredictionsWithId, Station};
+    use http::Request;
+    use uom::si::f64::Length;
+    use uom::si::length::meter;
+    use uuid::Uuid;
+
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::east(0).ymd(2019, 6, 1).and_hms(0, 0, 0)
+    }
+
+    /// Stations with predictions ending the given number of days from now.
+    fn catalogue(days_left: &[i64]) -> StationCatalogue {
+        let stations: Vec<_> = days_left
+            .iter()
+            .map(|_| Station {
+                name: "Station".to_string(),
+                coordinates: Coordinates { lat: 0.0, lon: 0.0 },
+                id: Uuid::new_v4(),
+                time_zone: None,
+            })
+            .collect();
+        let predictions = stations
+            .iter()
+            .zip(days_left)
+            .map(|(station, days)| PredictionsWithId {
+                station_id: station.id,
+                predictions: vec![TidePrediction {
+                    tide: Length::new::<meter>(1.0),
+                    time: now() + Duration::days(*days),
+                }],
+            })
+            .collect();
+        StationCatalogue::new(stations, predictions)
+    }
+
+    #[test]
+    fn it_should_need_enough_fresh_predictions() {
+        let readiness = Readiness {
+            min_days_ahead: 7,
+            min_fraction: 0.5,
+        };
+        let until = now() + Duration::days(7);
+
+        assert_eq!(
+            readiness.check(&catalogue(&[30, 10, 3, -1]), now()),
+            Check::Ready {
+                covered: 2,
+                stations: 4,
+                until
+            }
+        );
+        assert_eq!(
+            readiness.check(&catalogue(&[30, 6, 3, -1]), now()),
+            Check::ExpiringSoon {
+                covered: 1,
+                stations: 4,
+                until
+            }
+        );
+        assert_eq!(
+            readiness.check(&StationCatalogue::empty(), now()),
+            Check::NoStations
+        );
+    }
+
+    #[test]
+    fn it_should_answer_health_and_readiness_checks() {
+        let router = routes(
+            Router::new(),
+            SharedCatalogue::new(StationCatalogue::test()),
+            Readiness::default(),
+        );
+        let get = |uri| Request::builder().uri(uri).body(vec![]).unwrap();
+
+        assert_eq!(router.handle(&get("/healthz")).status(), StatusCode::OK);
+        let ready = router.handle(&get("/readyz"));
+        assert_eq!(ready.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(String::from_utf8_lossy(ready.body()).contains("only 0 of 1 stations"));
+    }
+}
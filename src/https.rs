@@ -0,0 +1,172 @@
+use http::header::{self, HeaderName, HeaderValue};
+use http::uri::Authority;
+use http::{Request, Response, StatusCode};
+use std::sync::Arc;
+
+use crate::router::{HandlerResult, Router};
+
+/// Set by the proxy in front of us to the scheme the client used.
+static FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// Where the site is served from in production.
+pub static CANONICAL_HOST: &str = "whattideisitrightnow.com";
+
+/// Sends visitors who arrived over plain HTTP to the same page over HTTPS,
+/// and tells browsers to keep using HTTPS.
+///
+/// TLS is handled by the proxy in front of the server, so the scheme comes
+/// from its `X-Forwarded-Proto` header. Requests without it are left alone.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Https {
+    /// The host to redirect to. The request's own `Host` is never used, so
+    /// a forged one can't send visitors to another site.
+    pub canonical_host: Authority,
+    /// How long browsers should remember to use HTTPS. `0` sends no
+    /// `Strict-Transport-Security` header.
+    pub hsts_max_age_secs: u64,
+}
+
+impl Default for Https {
+    fn default() -> Self {
+        Https {
+            canonical_host: Authority::from_static(CANONICAL_HOST),
+            hsts_max_age_secs: 0,
+        }
+    }
+}
+
+impl Https {
+    /// Add the redirect and HSTS header to every route of `router`.
+    pub fn wrap(self, router: Router) -> Router {
+        let https = Arc::new(self);
+        let finish = https.clone();
+        router
+            .before(move |request| https.redirect(request))
+            .after(move |request, response| finish.add_hsts(request, response))
+    }
+
+    /// The redirect for a plain HTTP request, keeping its path and query.
+    pub fn redirect<T>(&self, request: &Request<T>) -> Option<HandlerResult> {
+        if forwarded_proto(request)? != "http" {
+            return None;
+        }
+        let path = request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path| path.as_str());
+        Some(
+            Response::builder()
+                .status(StatusCode::MOVED_PERMANENTLY)
+                .header(
+                    header::LOCATION,
+                    format!("https://{}{}", self.canonical_host, path),
+                )
+                .body(vec![]),
+        )
+    }
+
+    pub fn add_hsts<T>(&self, request: &Request<T>, response: &mut Response<Vec<u8>>) {
+        if self.hsts_max_age_secs == 0 || forwarded_proto(request) != Some("https") {
+            return;
+        }
+        let value = format!("max-age={}", self.hsts_max_age_secs);
+        response.headers_mut().insert(
+            header::STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_str(&value).expect("max-age is a valid header value"),
+        );
+    }
+}
+
+fn forwarded_proto<T>(request: &Request<T>) -> Option<&str> {
+    request
+        .headers()
+        .get(HeaderName::from_static(FORWARDED_PROTO))?
+        .to_str()
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(proto: &str, host: &str, uri: &str) -> Request<Vec<u8>> {
+        Request::builder()
+            .uri(uri)
+            .header(FORWARDED_PROTO, proto)
+            .header(header::HOST, host)
+            .body(vec![])
+            .unwrap()
+    }
+
+    fn location(response: Option<HandlerResult>) -> String {
+        let response = response.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        response.headers()[header::LOCATION]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn it_should_keep_the_path_and_query() {
+        let https = Https::default();
+        let request = request(
+            "http",
+            "whattideisitrightnow.com",
+            "/station/abc/table?lat=49.3&lon=-123.2&offset=480",
+        );
+
+        assert_eq!(
+            location(https.redirect(&request)),
+            "https://whattideisitrightnow.com/station/abc/table?lat=49.3&lon=-123.2&offset=480"
+        );
+    }
+
+    #[test]
+    fn it_should_only_redirect_to_the_canonical_host() {
+        let https = Https::default();
+        for host in &["wtiirn.herokuapp.com", "evil.com", "evil.com@a.com"] {
+            assert_eq!(
+                location(https.redirect(&request("http", host, "/?lat=1"))),
+                "https://whattideisitrightnow.com/?lat=1"
+            );
+        }
+
+        let staging = Https {
+            canonical_host: "staging.example.com:8443".parse().unwrap(),
+            ..Https::default()
+        };
+        assert_eq!(
+            location(staging.redirect(&request("http", "evil.com", "/"))),
+            "https://staging.example.com:8443/"
+        );
+    }
+
+    #[test]
+    fn it_should_leave_other_requests_alone() {
+        let https = Https::default();
+
+        assert!(https.redirect(&request("https", "a.com", "/")).is_none());
+        let direct = Request::builder().uri("/").body(()).unwrap();
+        assert!(https.redirect(&direct).is_none());
+    }
+
+    #[test]
+    fn it_should_add_hsts_to_https_responses() {
+        let https = Https {
+            hsts_max_age_secs: 31_536_000,
+            ..Https::default()
+        };
+        let mut secure = Response::new(vec![]);
+        let mut plain = Response::new(vec![]);
+
+        https.add_hsts(&request("https", "a.com", "/"), &mut secure);
+        https.add_hsts(&request("http", "a.com", "/"), &mut plain);
+
+        assert_eq!(
+            secure.headers()[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=31536000"
+        );
+        assert!(plain.headers().is_empty());
+    }
+}
@@ -0,0 +1,534 @@
+//! Converting the files tide agencies publish into the JSON files under
+//! `data/` that `StationCatalogue::load` reads.
+
+use chrono::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uuid::Uuid;
+
+use crate::model::{Coordinates, TidePrediction};
+use crate::noaa_api::{self, transform};
+use crate::stations::{PredictionsWithId, Station};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    /// NOAA's annual high/low prediction XML files.
+    NoaaXml,
+    /// CSV from NOAA's CO-OPS data API (`format=csv`).
+    NoaaCsv,
+    /// JSON from NOAA's CO-OPS data API (`format=json`).
+    NoaaJson,
+    /// JSON predictions (`wlp` or `wlp-hilo`) from the DFO/CHS IWLS API.
+    ChsJson,
+}
+
+impl Format {
+    /// The prefix used for this source's files in `data/`.
+    pub fn default_prefix(self) -> &'static str {
+        match self {
+            Format::NoaaXml | Format::NoaaCsv | Format::NoaaJson => "usa_noaa",
+            Format::ChsJson => "ca_dfo",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "noaa-xml" => Ok(Format::NoaaXml),
+            "noaa-csv" => Ok(Format::NoaaCsv),
+            "noaa-json" => Ok(Format::NoaaJson),
+            "chs-json" => Ok(Format::ChsJson),
+            _ => Err(format!(
+                "unknown format {:?}, expected noaa-xml, noaa-csv, noaa-json or chs-json",
+                s
+            )),
+        }
+    }
+}
+
+/// Details of the station a file's predictions are for. Only NOAA's XML
+/// files describe their station, so the other formats need this.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StationInfo {
+    /// The agency's own id for the station, e.g. NOAA's `9414290` or the
+    /// CHS code `07795`.
+    pub source_id: u32,
+    pub name: String,
+    pub coordinates: Coordinates,
+}
+
+impl StationInfo {
+    fn to_station(&self, time_zone: Option<String>) -> Station {
+        Station {
+            name: self.name.clone(),
+            coordinates: self.coordinates,
+            id: Station::generate_id(&self.name, self.source_id),
+            time_zone,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImportOptions {
+    pub format: Format,
+    pub station: Option<StationInfo>,
+    /// IANA name of the station's time zone. Needed to read times in local
+    /// time, unless the file names the zone itself.
+    pub station_time_zone: Option<String>,
+    /// The `units` NOAA's data API was asked for: `english` or `metric`.
+    pub units: String,
+    /// The `time_zone` NOAA's data API was asked for: `gmt`, `lst` or
+    /// `lst_ldt`.
+    pub time_zone: String,
+}
+
+impl ImportOptions {
+    pub fn new(format: Format) -> Self {
+        ImportOptions {
+            format,
+            station: None,
+            station_time_zone: None,
+            units: "metric".to_string(),
+            time_zone: "gmt".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ImportError {
+    Parse(String),
+    /// The format doesn't describe its station, and none was given.
+    MissingStation,
+    Transform(transform::TransformError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Parse(e) => write!(f, "couldn't parse the file: {}", e),
+            ImportError::MissingStation => {
+                write!(f, "this format needs the station's id, name and location")
+            }
+            ImportError::Transform(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<transform::TransformError> for ImportError {
+    fn from(e: transform::TransformError) -> Self {
+        ImportError::Transform(e)
+    }
+}
+
+/// A station and its predictions, ready to be written to `data/`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    pub station: Station,
+    pub predictions: PredictionsWithId,
+}
+
+pub fn import(src: &str, options: &ImportOptions) -> Result<Import, ImportError> {
+    match options.format {
+        Format::NoaaXml => import_noaa_xml(src, options),
+        Format::NoaaCsv => {
+            let station = options
+                .station
+                .as_ref()
+                .ok_or(ImportError::MissingStation)?;
+            import_noaa_rows(parse_noaa_csv(src)?, station, options)
+        }
+        Format::NoaaJson => {
+            let station = options
+                .station
+                .as_ref()
+                .ok_or(ImportError::MissingStation)?;
+            import_noaa_rows(parse_noaa_json(src)?, station, options)
+        }
+        Format::ChsJson => {
+            let station = options
+                .station
+                .as_ref()
+                .ok_or(ImportError::MissingStation)?;
+            import_chs_json(src, station, options)
+        }
+    }
+}
+
+/// The XML files describe their station, but usually not its time zone,
+/// which is needed for files with times in `LST/LDT`. Without one from the
+/// options, the zone of the station's state is used.
+fn import_noaa_xml(src: &str, options: &ImportOptions) -> Result<Import, ImportError> {
+    let metadata = noaa_api::parse_high_low(src).map_err(|e| ImportError::Parse(e.to_string()))?;
+    let mut extracted = transform::extract_station(&metadata);
+    extracted.time_zone = extracted
+        .time_zone
+        .or_else(|| options.station_time_zone.clone())
+        .or_else(|| transform::state_time_zone(&metadata));
+    let predictions =
+        transform::extract_predictions_in_zone(&metadata, extracted.named_zone().as_ref())?
+            .into_iter()
+            .flat_map(|p| p.predictions)
+            .collect();
+    Ok(with_predictions(extracted, predictions))
+}
+
+/// A prediction as NOAA's data API writes it: local time text and height.
+struct NoaaRow {
+    time: String,
+    value: String,
+}
+
+fn parse_noaa_csv(src: &str) -> Result<Vec<NoaaRow>, ImportError> {
+    let mut lines = src
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    match lines.next() {
+        Some((_, header)) if header.trim_start().starts_with("Date Time") => {}
+        // NOAA's API reports problems as plain text instead of CSV.
+        Some((_, other)) => return Err(ImportError::Parse(other.trim().to_string())),
+        None => return Err(ImportError::Parse("the file is empty".to_string())),
+    }
+    lines
+        .map(|(i, line)| {
+            let mut fields = line.split(',').map(str::trim);
+            match (fields.next(), fields.next()) {
+                (Some(time), Some(value)) => Ok(NoaaRow {
+                    time: time.to_string(),
+                    value: value.to_string(),
+                }),
+                _ => Err(ImportError::Parse(format!(
+                    "line {} has no prediction: {:?}",
+                    i + 1,
+                    line
+                ))),
+            }
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct NoaaJson {
+    predictions: Option<Vec<NoaaJsonPrediction>>,
+    error: Option<NoaaJsonError>,
+}
+
+#[derive(Deserialize)]
+struct NoaaJsonPrediction {
+    t: String,
+    v: String,
+}
+
+#[derive(Deserialize)]
+struct NoaaJsonError {
+    message: String,
+}
+
+fn parse_noaa_json(src: &str) -> Result<Vec<NoaaRow>, ImportError> {
+    let json: NoaaJson =
+        serde_json::from_str(src).map_err(|e| ImportError::Parse(e.to_string()))?;
+    match (json.predictions, json.error) {
+        (Some(predictions), _) => Ok(predictions
+            .into_iter()
+            .map(|p| NoaaRow {
+                time: p.t,
+                value: p.v,
+            })
+            .collect()),
+        (None, Some(error)) => Err(ImportError::Parse(error.message)),
+        (None, None) => Err(ImportError::Parse("no predictions".to_string())),
+    }
+}
+
+fn import_noaa_rows(
+    rows: Vec<NoaaRow>,
+    station: &StationInfo,
+    options: &ImportOptions,
+) -> Result<Import, ImportError> {
+    let to_length = transform::length_unit(match options.units.as_str() {
+        "english" => "feet",
+        "metric" => "meters",
+        other => other,
+    })?;
+    let declared = match options.time_zone.as_str() {
+        "gmt" => "GMT",
+        "lst" => "LST",
+        "lst_ldt" => "LST/LDT",
+        other => other,
+    };
+
+    let station = station.to_station(options.station_time_zone.clone());
+    let station_zone = station.named_zone();
+    let mut zone = None;
+    let predictions = rows
+        .iter()
+        .map(|row| {
+            let invalid = || transform::TransformError::InvalidDateTime {
+                date: row.time.clone(),
+                time: String::new(),
+            };
+            let naive = NaiveDateTime::parse_from_str(&row.time, "%Y-%m-%d %H:%M")
+                .map_err(|_| invalid())?;
+            if zone.is_none() {
+                zone = Some(transform::declared_zone(
+                    declared,
+                    station_zone.as_ref(),
+                    naive.year(),
+                )?);
+            }
+            let time = zone
+                .as_ref()
+                .and_then(|zone| transform::localize(&naive, zone))
+                .ok_or_else(|| transform::TransformError::NonexistentLocalTime {
+                    date: row.time.clone(),
+                    time: String::new(),
+                })?;
+            let value: f64 = row
+                .value
+                .parse()
+                .map_err(|_| ImportError::Parse(format!("bad height {:?}", row.value)))?;
+            Ok(TidePrediction {
+                tide: to_length(value),
+                time,
+            })
+        })
+        .collect::<Result<_, ImportError>>()?;
+    Ok(with_predictions(station, predictions))
+}
+
+/// An event from the IWLS API's `/stations/{id}/data` endpoint. Heights
+/// are in metres and times in UTC.
+#[derive(Deserialize)]
+struct ChsEvent {
+    #[serde(rename = "eventDate")]
+    event_date: DateTime<Utc>,
+    value: f64,
+}
+
+fn import_chs_json(
+    src: &str,
+    station: &StationInfo,
+    options: &ImportOptions,
+) -> Result<Import, ImportError> {
+    let events: Vec<ChsEvent> =
+        serde_json::from_str(src).map_err(|e| ImportError::Parse(e.to_string()))?;
+    let predictions = events
+        .into_iter()
+        .map(|event| TidePrediction {
+            tide: Length::new::<meter>(event.value),
+            time: event.event_date.with_timezone(&FixedOffset::east(0)),
+        })
+        .collect();
+    let station = station.to_station(options.station_time_zone.clone());
+    Ok(with_predictions(station, predictions))
+}
+
+fn with_predictions(station: Station, mut predictions: Vec<TidePrediction>) -> Import {
+    predictions.sort_by_key(|p| p.time);
+    Import {
+        predictions: PredictionsWithId {
+            station_id: station.id,
+            predictions,
+        },
+        station,
+    }
+}
+
+/// Write imported stations and predictions into a data directory, laid
+/// out the way `StationCatalogue::load` expects: stations are merged into
+/// `stations/{prefix}_stations.json`, and each station's predictions are
+/// merged into `predictions/{prefix}_{id}.json`.
+pub fn write_catalogue(
+    data_dir: &Path,
+    prefix: &str,
+    imports: &[Import],
+) -> Result<(), Box<dyn Error>> {
+    let stations_dir = data_dir.join("stations");
+    let predictions_dir = data_dir.join("predictions");
+    fs::create_dir_all(&stations_dir)?;
+    fs::create_dir_all(&predictions_dir)?;
+
+    let stations_path = stations_dir.join(format!("{}_stations.json", prefix));
+    let mut stations: Vec<Station> = read_json_or_empty(&stations_path)?;
+    for import in imports {
+        match stations.iter_mut().find(|s| s.id == import.station.id) {
+            Some(existing) => {
+                // Keep a zone filled in by hand when the import doesn't name one.
+                let time_zone = existing.time_zone.take();
+                *existing = import.station.clone();
+                existing.time_zone = existing.time_zone.take().or(time_zone);
+            }
+            None => stations.push(import.station.clone()),
+        }
+    }
+    fs::write(&stations_path, serde_json::to_string(&stations)?)?;
+
+    let mut by_station: HashMap<Uuid, Vec<TidePrediction>> = HashMap::new();
+    for import in imports {
+        by_station
+            .entry(import.station.id)
+            .or_default()
+            .extend(&import.predictions.predictions);
+    }
+    for (station_id, mut predictions) in by_station {
+        let path = predictions_dir.join(format!("{}_{}.json", prefix, station_id));
+        let existing: Vec<PredictionsWithId> = read_json_or_empty(&path)?;
+        predictions.extend(existing.into_iter().flat_map(|p| p.predictions));
+        predictions.sort_by_key(|p| p.time);
+        predictions.dedup_by_key(|p| p.time);
+        let file = vec![PredictionsWithId {
+            station_id,
+            predictions,
+        }];
+        fs::write(&path, serde_json::to_string(&file)?)?;
+    }
+    Ok(())
+}
+
+fn read_json_or_empty<T: serde::de::DeserializeOwned>(
+    path: &Path,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stations;
+    use uom::si::length::foot;
+
+    fn station_info() -> StationInfo {
+        StationInfo {
+            source_id: 7795,
+            name: "Vancouver".to_string(),
+            coordinates: Coordinates {
+                lat: 49.2868,
+                lon: -123.1117,
+            },
+        }
+    }
+
+    fn options(format: Format) -> ImportOptions {
+        ImportOptions {
+            station: Some(station_info()),
+            station_time_zone: Some("America/Vancouver".to_string()),
+            ..ImportOptions::new(format)
+        }
+    }
+
+    #[test]
+    fn it_should_import_noaa_xml() {
+        let src = fs::read_to_string("test_data/noaa/good/9414290.xml").unwrap();
+        let import = import(&src, &ImportOptions::new(Format::NoaaXml)).unwrap();
+
+        assert_eq!(import.station.name, "San Francisco");
+        assert_eq!(import.predictions.station_id, import.station.id);
+        assert_eq!(import.predictions.predictions.len(), 8);
+    }
+
+    #[test]
+    fn it_should_import_noaa_csv() {
+        let src = "Date Time, Prediction, Type\n\
+                   2019-07-01 04:27,5.523,H\n\
+                   2019-07-01 10:51,0.712,L\n";
+        let mut options = options(Format::NoaaCsv);
+        options.units = "english".to_string();
+        options.time_zone = "lst_ldt".to_string();
+
+        let import = import(src, &options).unwrap();
+
+        assert_eq!(import.station.id, Station::generate_id("Vancouver", 7795));
+        let first = import.predictions.predictions[0];
+        assert_eq!(first.tide, Length::new::<foot>(5.523));
+        assert_eq!(
+            first.time,
+            FixedOffset::west(7 * 3600)
+                .ymd(2019, 7, 1)
+                .and_hms(4, 27, 0)
+        );
+    }
+
+    #[test]
+    fn it_should_import_noaa_json() {
+        let src = r#"{"predictions":[
+            {"t":"2019-01-01 10:51","v":"0.217","type":"L"},
+            {"t":"2019-01-01 04:27","v":"1.683","type":"H"}]}"#;
+
+        let import = import(src, &options(Format::NoaaJson)).unwrap();
+
+        let predictions = &import.predictions.predictions;
+        assert_eq!(predictions.len(), 2);
+        assert_eq!(predictions[0].tide, Length::new::<meter>(1.683));
+        assert_eq!(predictions[0].time, Utc.ymd(2019, 1, 1).and_hms(4, 27, 0));
+    }
+
+    #[test]
+    fn it_should_report_noaa_api_errors() {
+        let src = r#"{"error":{"message":"No Predictions data was found."}}"#;
+        assert_eq!(
+            import(src, &options(Format::NoaaJson)),
+            Err(ImportError::Parse(
+                "No Predictions data was found.".to_string()
+            ))
+        );
+        assert_eq!(
+            import(src, &ImportOptions::new(Format::NoaaJson)),
+            Err(ImportError::MissingStation)
+        );
+    }
+
+    #[test]
+    fn it_should_import_chs_json() {
+        let src = r#"[
+            {"eventDate":"2019-09-08T22:54:00Z","qcFlagCode":"1","value":2.5,"timeSeriesId":"x"},
+            {"eventDate":"2019-09-09T04:55:00Z","qcFlagCode":"1","value":4.1,"timeSeriesId":"x"}]"#;
+
+        let import = import(src, &options(Format::ChsJson)).unwrap();
+
+        let predictions = &import.predictions.predictions;
+        assert_eq!(predictions[1].tide, Length::new::<meter>(4.1));
+        assert_eq!(predictions[1].time, Utc.ymd(2019, 9, 9).and_hms(4, 55, 0));
+    }
+
+    #[test]
+    fn it_should_write_files_the_catalogue_can_load() {
+        let dir = std::env::temp_dir().join(format!("wtiirn-import-{}", Uuid::new_v4()));
+        let src = r#"[{"eventDate":"2019-09-08T22:54:00Z","value":2.5}]"#;
+        let first = import(src, &options(Format::ChsJson)).unwrap();
+        let src = r#"[{"eventDate":"2019-09-09T04:55:00Z","value":4.1}]"#;
+        let mut without_zone = options(Format::ChsJson);
+        without_zone.station_time_zone = None;
+        let second = import(src, &without_zone).unwrap();
+
+        write_catalogue(&dir, "ca_dfo", std::slice::from_ref(&first)).unwrap();
+        write_catalogue(&dir, "ca_dfo", &[second]).unwrap();
+
+        let mut report = stations::report::LoadReport::default();
+        let loaded_stations =
+            stations::load_stations_from_dir(&dir.join("stations"), &mut report).unwrap();
+        let loaded_predictions =
+            stations::load_predictions_from_dir(&dir.join("predictions"), &mut report).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(report.is_clean());
+        assert_eq!(loaded_stations, vec![first.station.clone()]);
+        assert_eq!(loaded_predictions.len(), 1);
+        assert_eq!(loaded_predictions[0].station_id, first.station.id);
+        assert_eq!(loaded_predictions[0].predictions.len(), 2);
+    }
+}
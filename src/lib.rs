@@ -1,5 +1,30 @@
-mod compute;
+// Some of the older tests are written in ways newer versions of clippy
+// warn about. They're left as they are.
+#![cfg_attr(
+    test,
+    allow(
+        clippy::bool_assert_comparison,
+        clippy::needless_borrow,
+        clippy::useless_vec,
+        clippy::zero_prefixed_literal
+    )
+)]
+
+pub mod api;
+mod chart;
+pub mod compute;
+pub mod config;
+pub mod health;
+pub mod https;
+pub mod import;
+pub mod logging;
+pub mod metrics;
 pub mod model;
 pub mod noaa_api;
 pub mod pages;
+pub mod reload;
+pub mod router;
+pub mod server;
+pub mod static_files;
 pub mod stations;
+pub mod zone;
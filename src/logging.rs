@@ -0,0 +1,228 @@
+//! Log output for the server, as text or as one JSON object per line, and
+//! the access log with a line for every request.
+
+use chrono::prelude::*;
+use http::{Request, Response};
+use log::{info, Record};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The target access log lines are logged under, so they can be filtered
+/// separately, e.g. `info,access=off`.
+pub static ACCESS_TARGET: &str = "access";
+
+static JSON_LINES: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, for log aggregation.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}", s)),
+        }
+    }
+}
+
+/// Send log records matching `filter`, an `env_logger` filter such as
+/// `info` or `wtiirn=debug`, to stdout.
+pub fn init(filter: &str, format: LogFormat) {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .parse_filters(filter)
+        .target(env_logger::Target::Stdout);
+    if format == LogFormat::Json {
+        JSON_LINES.store(true, Ordering::SeqCst);
+        builder.format(|buf, record| writeln!(buf, "{}", json_line(record, Utc::now())));
+    }
+    builder.init();
+}
+
+fn json_line(record: &Record, time: DateTime<Utc>) -> Value {
+    let message = record.args().to_string();
+    let mut line = Map::new();
+    line.insert("time".to_string(), json!(time.to_rfc3339()));
+    line.insert("level".to_string(), json!(record.level().to_string()));
+    line.insert("target".to_string(), json!(record.target()));
+    // Access log messages are already JSON; lift their fields up so
+    // they can be queried directly.
+    match serde_json::from_str::<Map<String, Value>>(&message) {
+        Ok(fields) if record.target() == ACCESS_TARGET => line.extend(fields),
+        _ => {
+            line.insert("message".to_string(), json!(message));
+        }
+    }
+    Value::Object(line)
+}
+
+/// The station a response was about. Handlers add this to their response's
+/// extensions so it's included in the access log.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ChosenStation(pub Uuid);
+
+/// One request, as recorded in the access log.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Access {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    pub station: Option<Uuid>,
+}
+
+impl Access {
+    pub fn new<T, U>(request: &Request<T>, response: &Response<U>, latency: Duration) -> Self {
+        Access {
+            method: request.method().to_string(),
+            path: request.uri().path().to_string(),
+            status: response.status().as_u16(),
+            latency_ms: latency.as_secs_f64() * 1000.0,
+            station: response
+                .extensions()
+                .get::<ChosenStation>()
+                .map(|station| station.0),
+        }
+    }
+
+    /// A response sent without a request to go with it, because the
+    /// server was too busy or couldn't read one.
+    pub fn rejected<U>(response: &Response<U>, latency: Duration) -> Self {
+        Access {
+            method: "-".to_string(),
+            path: "-".to_string(),
+            status: response.status().as_u16(),
+            latency_ms: latency.as_secs_f64() * 1000.0,
+            station: None,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {:.1}ms",
+            self.method, self.path, self.status, self.latency_ms
+        )?;
+        if let Some(station) = self.station {
+            write!(f, " station={}", station)?;
+        }
+        Ok(())
+    }
+}
+
+/// Add a request to the access log.
+pub fn access(entry: &Access) {
+    if JSON_LINES.load(Ordering::SeqCst) {
+        let fields = serde_json::to_string(entry).expect("access entries serialize");
+        info!(target: ACCESS_TARGET, "{}", fields);
+    } else {
+        info!(target: ACCESS_TARGET, "{}", entry);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::StatusCode;
+    use log::Level;
+
+    fn entry() -> Access {
+        let request = Request::builder()
+            .method("GET")
+            .uri("/station/abc/table?days=3")
+            .body(())
+            .unwrap();
+        let station = Uuid::nil();
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .extension(ChosenStation(station))
+            .body(())
+            .unwrap();
+        Access::new(&request, &response, Duration::from_micros(12_500))
+    }
+
+    #[test]
+    fn it_should_describe_requests() {
+        let entry = entry();
+
+        assert_eq!(
+            entry,
+            Access {
+                method: "GET".to_string(),
+                path: "/station/abc/table".to_string(),
+                status: 200,
+                latency_ms: 12.5,
+                station: Some(Uuid::nil()),
+            }
+        );
+        assert_eq!(
+            entry.to_string(),
+            "GET /station/abc/table 200 12.5ms station=00000000-0000-0000-0000-000000000000"
+        );
+    }
+
+    #[test]
+    fn it_should_describe_rejected_connections() {
+        let response = Response::builder()
+            .status(StatusCode::REQUEST_TIMEOUT)
+            .body(())
+            .unwrap();
+
+        let entry = Access::rejected(&response, Duration::from_secs(30));
+
+        assert_eq!(entry.to_string(), "- - 408 30000.0ms");
+    }
+
+    #[test]
+    fn it_should_write_json_lines() {
+        let time = Utc.ymd(2019, 6, 1).and_hms(12, 0, 0);
+        let fields = serde_json::to_string(&entry()).unwrap();
+
+        let access = json_line(
+            &Record::builder()
+                .level(Level::Info)
+                .target(ACCESS_TARGET)
+                .args(format_args!("{}", fields))
+                .build(),
+            time,
+        );
+        let other = json_line(
+            &Record::builder()
+                .level(Level::Warn)
+                .target("wtiirn::reload")
+                .args(format_args!("Keeping the previous station catalogue"))
+                .build(),
+            time,
+        );
+
+        assert_eq!(access["time"], "2019-06-01T12:00:00+00:00");
+        assert_eq!(access["status"], 200);
+        assert_eq!(access["path"], "/station/abc/table");
+        assert_eq!(access["station"], Uuid::nil().to_string());
+        assert_eq!(
+            other,
+            json!({
+                "time": "2019-06-01T12:00:00+00:00",
+                "level": "WARN",
+                "target": "wtiirn::reload",
+                "message": "Keeping the previous station catalogue",
+            })
+        );
+    }
+}
@@ -1,87 +1,540 @@
-use std::env;
-use wtiirn::{pages, stations};
+use std::net::TcpListener;
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wtiirn::config::Config;
+use wtiirn::logging::ChosenStation;
+use wtiirn::metrics::{self, Metrics, NoTideInformation};
+use wtiirn::router::{HandlerResult, Params, Router};
+use wtiirn::{api, health, logging, pages, reload, server, static_files, stations};
 
-use http::header::{self, HeaderName};
-use simple_server::{Handler, Method, Request, ResponseBuilder, Server, StatusCode};
+use http::header;
+use http::{Method, Request, Response, StatusCode};
+use log::{error, info, warn};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
 
 fn main() {
-    println!("WTIIRN booting up!");
-    let host = env::var("WTIIRN_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port = env::var("PORT").unwrap_or_else(|_| "7878".to_string());
+    let config = match Config::from_environment() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    logging::init(&config.log_level, config.log_format);
+    let defaults = config
+        .page_defaults()
+        .expect("the defaults were checked when the config was loaded");
+    let https = config
+        .https()
+        .expect("the https settings were checked when the config was loaded");
 
-    let catalogue = stations::StationCatalogue::load();
+    info!("WTIIRN booting up!");
+    let (host, port) = config.bind_address();
 
-    println!("Starting Server");
-    let server = Server::new(routes(catalogue));
+    let (catalogue, report) = stations::StationCatalogue::load_with_report(&config.data);
+    report.log();
+    if config.strict && !report.is_clean() {
+        error!("Refusing to start: some data files are malformed (strict mode)");
+        process::exit(1);
+    }
+    if catalogue.is_empty() {
+        warn!("No stations were loaded; /readyz will fail until data is available");
+    }
+
+    let catalogue = reload::SharedCatalogue::new(catalogue);
+    if config.reload_secs > 0 {
+        let interval = Duration::from_secs(config.reload_secs);
+        let strict = config.strict;
+        let paths = config.data.clone();
+        reload::watch(catalogue.clone(), paths.dirs(), interval, move || {
+            match stations::StationCatalogue::try_load(&paths) {
+                Ok((catalogue, report)) => {
+                    report.log();
+                    if catalogue.is_empty() || (strict && !report.is_clean()) {
+                        None
+                    } else {
+                        Some(catalogue)
+                    }
+                }
+                Err(e) => {
+                    error!("Could not reload the station catalogue: {}", e);
+                    None
+                }
+            }
+        });
+    }
 
-    println!("Server listening on port: {}", port);
-    server.listen(&host, &port);
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in &[signal_hook::SIGTERM, signal_hook::SIGINT] {
+        signal_hook::flag::register(*signal, shutdown.clone())
+            .expect("Could not listen for shutdown signals");
+    }
+
+    info!("Starting Server");
+    let listener = match TcpListener::bind(format!("{}:{}", host, port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Could not listen on {}:{}: {}", host, port, e);
+            process::exit(1);
+        }
+    };
+    let metrics = Arc::new(Metrics::new());
+    let app = routes(catalogue.clone(), defaults).get("/metrics", {
+        let (metrics, catalogue) = (metrics.clone(), catalogue.clone());
+        move |_, _| {
+            Response::builder()
+                .header(header::CONTENT_TYPE, metrics::CONTENT_TYPE)
+                .body(metrics.render(&catalogue.get()).into_bytes())
+        }
+    });
+    let app = health::routes(app, catalogue, config.readiness);
+    let app = match https {
+        Some(https) => https.wrap(app),
+        None => app,
+    };
+
+    info!("Server listening on port: {}", port);
+    let rejected_metrics = metrics.clone();
+    let handler = move |request: &Request<Vec<u8>>| {
+        let started = Instant::now();
+        let response = app.handle(request);
+        let latency = started.elapsed();
+        logging::access(&logging::Access::new(request, &response, latency));
+        metrics.record(&response, latency);
+        response
+    };
+    let rejected = move |response: &Response<Vec<u8>>, latency| {
+        logging::access(&logging::Access::rejected(response, latency));
+        rejected_metrics.record(response, latency);
+    };
+    if let Err(e) = server::serve(
+        listener,
+        &config.server_options(),
+        handler,
+        rejected,
+        &shutdown,
+    ) {
+        error!("Server failed: {}", e);
+        process::exit(1);
+    }
+    info!("Server stopped");
 }
 
-fn routes(catalogue: stations::StationCatalogue) -> Handler {
-    let forwarded_proto_header = HeaderName::from_static("x-forwarded-proto");
-
-    Box::new(
-        move |request: Request<Vec<u8>>, mut response: ResponseBuilder| {
-            println!("Request received. {} {}", request.method(), request.uri());
-            let query = request.uri().query().unwrap_or_else(|| "");
-            let params = serde_urlencoded::from_str::<pages::HomePageParams>(query).ok();
-
-            match (
-                request.method(),
-                request.uri().path(),
-                request
-                    .headers()
-                    .get(&forwarded_proto_header)
-                    .map(|x| x.len()),
-            ) {
-                (_, _, Some(4)) => {
-                    response
-                        .status(StatusCode::MOVED_PERMANENTLY)
-                        .header(header::LOCATION, "https://whattideisitrightnow.com");
-                    Ok(response.body(vec![])?)
+fn routes(catalogue: reload::SharedCatalogue, defaults: pages::Defaults) -> Router {
+    let defaults = Arc::new(defaults);
+    let static_files = static_files::StaticFiles::new("public");
+
+    let home = {
+        let (catalogue, defaults) = (catalogue.clone(), defaults.clone());
+        move |request: &Request<Vec<u8>>, _: &Params| {
+            let catalogue = catalogue.get();
+            if catalogue.is_empty() {
+                return unavailable();
+            }
+            let params = query_params::<pages::HomePageParams>(request);
+            let vm = pages::HomePageViewModel::new(&catalogue, &params, &defaults);
+            let shown = Shown::from(&vm);
+            shown.annotate(Response::builder().body(pages::home_page(vm).into_bytes()))
+        }
+    };
+    let tide_api = {
+        let (catalogue, defaults) = (catalogue.clone(), defaults.clone());
+        move |request: &Request<Vec<u8>>, _: &Params| {
+            let catalogue = catalogue.get();
+            if catalogue.is_empty() {
+                return unavailable();
+            }
+            let params = query_params::<pages::HomePageParams>(request);
+            let vm = pages::HomePageViewModel::new(&catalogue, &params, &defaults);
+            Shown::from(&vm).annotate(
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(api::TideResponse::new(&vm).to_json().into_bytes()),
+            )
+        }
+    };
+    let station_home = {
+        let (catalogue, defaults) = (catalogue.clone(), defaults.clone());
+        move |request: &Request<Vec<u8>>, path: &Params| {
+            let catalogue = catalogue.get();
+            match find_station(&catalogue, path) {
+                Some(station) => {
+                    let params = query_params::<pages::HomePageParams>(request);
+                    let vm = pages::HomePageViewModel::for_station(
+                        &catalogue, station, &params, &defaults,
+                    );
+                    let shown = Shown::from(&vm);
+                    shown.annotate(Response::builder().body(pages::home_page(vm).into_bytes()))
                 }
-                (&Method::GET, "/", _) => Ok(response.body(
-                    pages::home_page(pages::HomePageViewModel::new(&catalogue, &params))
-                        .as_bytes()
-                        .to_vec(),
-                )?),
-                (_, _, _) => {
-                    response.status(StatusCode::NOT_FOUND);
-                    Ok(response.body(pages::not_found_page().as_bytes().to_vec())?)
+                None => not_found(),
+            }
+        }
+    };
+    let station_table = move |request: &Request<Vec<u8>>, path: &Params| {
+        let catalogue = catalogue.get();
+        match find_station(&catalogue, path) {
+            Some(station) => {
+                let params = query_params::<pages::TablePageParams>(request);
+                let vm = pages::TablePageViewModel::new(station, &catalogue, &params, &defaults);
+                Response::builder()
+                    .extension(ChosenStation(station.id))
+                    .body(pages::table_page(vm).into_bytes())
+            }
+            None => not_found(),
+        }
+    };
+
+    Router::new()
+        .get("/", home)
+        .get("/api/v1/tide", tide_api)
+        .get("/station/:id", station_home)
+        .get("/station/:id/table", station_table)
+        .fallback(move |request, _| {
+            let method = request.method();
+            match static_files.resolve(request.uri().path()) {
+                Some(file) if method == Method::GET || method == Method::HEAD => {
+                    static_files.serve(&file, request, &mut Response::builder())
                 }
+                _ => not_found(),
             }
-        },
-    )
+        })
+}
+
+/// What a home page or API response showed, for the access log and
+/// metrics.
+struct Shown {
+    station: Uuid,
+    has_tide_information: bool,
+}
+
+impl Shown {
+    fn from(vm: &pages::HomePageViewModel) -> Self {
+        Shown {
+            station: vm.station().id,
+            has_tide_information: vm.has_tide_information(),
+        }
+    }
+
+    fn annotate(self, response: HandlerResult) -> HandlerResult {
+        let mut response = response?;
+        let extensions = response.extensions_mut();
+        extensions.insert(ChosenStation(self.station));
+        if !self.has_tide_information {
+            extensions.insert(NoTideInformation);
+        }
+        Ok(response)
+    }
+}
+
+fn query_params<T: DeserializeOwned>(request: &Request<Vec<u8>>) -> Option<T> {
+    serde_urlencoded::from_str(request.uri().query().unwrap_or("")).ok()
+}
+
+/// The station named by a route's `:id`.
+fn find_station<'a>(
+    catalogue: &'a stations::StationCatalogue,
+    path: &Params,
+) -> Option<&'a stations::Station> {
+    let id = Uuid::parse_str(path.get("id")?).ok()?;
+    catalogue.find_by_id(&id)
+}
+
+fn not_found() -> HandlerResult {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(pages::not_found_page().into_bytes())
+}
+
+/// For pages that need at least one station when none are loaded.
+fn unavailable() -> HandlerResult {
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(b"No tide data is loaded right now. Please try again later.".to_vec())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use simple_server::Response;
+    use wtiirn::https::Https;
+    use wtiirn::model::Coordinates;
     #[test]
     fn it_should_redirect_in_http() {
-        let routes = routes(stations::StationCatalogue::test());
+        let routes = Https::default().wrap(routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::test()),
+            pages::Defaults::default(),
+        ));
         let request = Request::builder()
+            .uri("/api/v1/tide?lat=1.0&lon=1.0")
             .header("x-forwarded-proto", "http")
+            .header(header::HOST, "whattideisitrightnow.com")
             .body(vec![])
             .unwrap();
 
-        let response = routes(request, Response::builder()).unwrap();
+        let response = routes.handle(&request);
 
         assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://whattideisitrightnow.com/api/v1/tide?lat=1.0&lon=1.0"
+        );
     }
 
     #[test]
     fn it_should_not_redirect_in_https() {
-        let routes = routes(stations::StationCatalogue::test());
+        let https = Https {
+            hsts_max_age_secs: 60,
+            ..Https::default()
+        };
+        let routes = https.wrap(routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::test()),
+            pages::Defaults::default(),
+        ));
         let request = Request::builder()
             .header("x-forwarded-proto", "https")
             .body(vec![])
             .unwrap();
 
-        let response = routes(request, Response::builder()).unwrap();
+        let response = routes.handle(&request);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=60"
+        );
+    }
+
+    #[test]
+    fn it_should_serve_the_tide_api_as_json() {
+        let routes = routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::test()),
+            pages::Defaults::default(),
+        );
+        let request = Request::builder()
+            .uri("/api/v1/tide?lat=1.0&lon=1.0&offset=0")
+            .body(vec![])
+            .unwrap();
+
+        let response = routes.handle(&request);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["station"]["name"], "Test Station");
+        assert!(body["distance_to_station_m"].as_f64().unwrap() > 0.0);
+        // The test station has no predictions.
+        assert!(response.extensions().get::<NoTideInformation>().is_some());
+    }
+
+    #[test]
+    fn it_should_use_the_requested_time_zone() {
+        let routes = routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::test()),
+            pages::Defaults::default(),
+        );
+        let request = Request::builder()
+            .uri("/api/v1/tide?tz=America/Halifax&offset=0")
+            .body(vec![])
+            .unwrap();
+
+        let response = routes.handle(&request);
+
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["time_zone"], "America/Halifax");
+    }
+
+    #[test]
+    fn it_should_ignore_offsets_out_of_range() {
+        let routes = routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::test()),
+            pages::Defaults::default(),
+        );
+        let request = Request::builder()
+            .uri("/api/v1/tide?offset=50000000")
+            .body(vec![])
+            .unwrap();
+
+        let response = routes.handle(&request);
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[test]
+    fn it_should_serve_the_tide_table_for_a_station() {
+        let catalogue = stations::StationCatalogue::test();
+        let id = catalogue.find_near(&Coordinates { lat: 0.0, lon: 0.0 }).id;
+        let routes = routes(
+            reload::SharedCatalogue::new(catalogue),
+            pages::Defaults::default(),
+        );
+        let request = Request::builder()
+            .uri(format!("/station/{}/table?days=3", id))
+            .body(vec![])
+            .unwrap();
+
+        let response = routes.handle(&request);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.body().clone()).unwrap();
+        assert!(body.contains("Tide Table for Test Station"));
+    }
+
+    #[test]
+    fn it_should_keep_the_viewers_zone_in_table_links() {
+        let catalogue = stations::StationCatalogue::test();
+        let id = catalogue.find_near(&Coordinates { lat: 0.0, lon: 0.0 }).id;
+        let routes = routes(
+            reload::SharedCatalogue::new(catalogue),
+            pages::Defaults::default(),
+        );
+        for (query, shown) in &[
+            ("offset=-480", "UTC+08:00"),
+            ("tz=Etc/GMT%2B8", "Etc/GMT+8"),
+        ] {
+            let request = Request::builder()
+                .uri(format!("/station/{}?{}", id, query))
+                .body(vec![])
+                .unwrap();
+            let body = String::from_utf8(routes.handle(&request).body().clone()).unwrap();
+            let start = body.find(&format!("/station/{}/table?", id)).unwrap();
+            let link = &body[start..start + body[start..].find('\'').unwrap()];
+
+            let request = Request::builder().uri(link).body(vec![]).unwrap();
+            let response = routes.handle(&request);
+
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = String::from_utf8(response.body().clone()).unwrap();
+            assert!(
+                body.contains(&format!("All times are in {}", shown)),
+                "{}",
+                link
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_keep_the_viewers_zone_in_nearby_station_links() {
+        let stations = stations::load_stations_from_dir(
+            std::path::Path::new("test_data/stations/good"),
+            &mut stations::report::LoadReport::default(),
+        )
+        .unwrap();
+        let routes = routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::new(stations, vec![])),
+            pages::Defaults::default(),
+        );
+        let request = Request::builder()
+            .uri("/?lat=49.19&lon=-123.18&offset=-480")
+            .body(vec![])
+            .unwrap();
+        let body = String::from_utf8(routes.handle(&request).body().clone()).unwrap();
+        let nearby = &body[body.find("Nearby stations").unwrap()..];
+        let start = nearby.find("/station/").unwrap();
+        let link = &nearby[start..start + nearby[start..].find('\'').unwrap()];
+
+        let request = Request::builder().uri(link).body(vec![]).unwrap();
+        let response = routes.handle(&request);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.body().clone()).unwrap();
+        assert!(body.contains("/table?days=7&offset=-480'"), "{}", link);
+    }
+
+    #[test]
+    fn it_should_not_find_pages_for_unknown_stations() {
+        let routes = routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::test()),
+            pages::Defaults::default(),
+        );
+        for uri in &[
+            format!("/station/{}/table", Uuid::nil()),
+            format!("/station/{}", Uuid::nil()),
+            format!("/station/{}/nope", Uuid::new_v4()),
+            "/station/not-a-uuid/table".to_string(),
+        ] {
+            let request = Request::builder().uri(uri.as_str()).body(vec![]).unwrap();
+
+            let response = routes.handle(&request);
+
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn it_should_serve_the_home_page_for_a_chosen_station() {
+        let catalogue = stations::StationCatalogue::test();
+        let id = catalogue.find_near(&Coordinates { lat: 0.0, lon: 0.0 }).id;
+        let routes = routes(
+            reload::SharedCatalogue::new(catalogue),
+            pages::Defaults::default(),
+        );
+        let request = Request::builder()
+            .uri(format!("/station/{}?lat=45.0&lon=45.0", id))
+            .body(vec![])
+            .unwrap();
+
+        let response = routes.handle(&request);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.body().clone()).unwrap();
+        assert!(body.contains("<b>Test Station</b>"));
+        assert_eq!(
+            response.extensions().get::<ChosenStation>(),
+            Some(&ChosenStation(id))
+        );
+    }
+
+    #[test]
+    fn it_should_be_unavailable_without_stations() {
+        let routes = routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::empty()),
+            pages::Defaults::default(),
+        );
+        for uri in &["/", "/api/v1/tide"] {
+            let request = Request::builder().uri(*uri).body(vec![]).unwrap();
+
+            let response = routes.handle(&request);
+
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+    }
+
+    #[test]
+    fn it_should_serve_static_files_from_public() {
+        let routes = routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::test()),
+            pages::Defaults::default(),
+        );
+        let request = Request::builder().uri("/style.css").body(vec![]).unwrap();
+
+        let response = routes.handle(&request);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/css; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn it_should_not_serve_files_outside_of_public() {
+        let routes = routes(
+            reload::SharedCatalogue::new(stations::StationCatalogue::test()),
+            pages::Defaults::default(),
+        );
+        let request = Request::builder()
+            .uri("/../Cargo.toml")
+            .body(vec![])
+            .unwrap();
+
+        let response = routes.handle(&request);
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,255 @@
+//! Counters for `/metrics`, in the Prometheus text format.
+
+use http::Response;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::router::MatchedRoute;
+use crate::stations::StationCatalogue;
+
+pub static CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Upper bounds, in seconds, of the request latency histogram's buckets.
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// The route label for responses that didn't come from a route, such as
+/// static files, redirects and 404s.
+static OTHER_ROUTE: &str = "other";
+
+/// Marks a response that had no tide to show, because the catalogue has no
+/// predictions around the current time for the station.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NoTideInformation;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    latencies: Mutex<BTreeMap<String, Histogram>>,
+    no_tide_information: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// How many observations fell in each of `LATENCY_BUCKETS`, not
+    /// counting smaller buckets.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        self.buckets.resize(LATENCY_BUCKETS.len(), 0);
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| value <= *le) {
+            self.buckets[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Count a response and how long it took.
+    pub fn record<T>(&self, response: &Response<T>, latency: Duration) {
+        let route = response
+            .extensions()
+            .get::<MatchedRoute>()
+            .map_or(OTHER_ROUTE, |route| route.0.as_str())
+            .to_string();
+        let status = response.status().as_u16();
+
+        *lock(&self.requests)
+            .entry((route.clone(), status))
+            .or_default() += 1;
+        lock(&self.latencies)
+            .entry(route)
+            .or_default()
+            .observe(latency.as_secs_f64());
+        if response.extensions().get::<NoTideInformation>().is_some() {
+            self.no_tide_information.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Everything counted so far, and the size of `catalogue`.
+    pub fn render(&self, catalogue: &StationCatalogue) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "wtiirn_http_requests_total",
+            "counter",
+            "Responses sent, by route and status.",
+        );
+        for ((route, status), count) in lock(&self.requests).iter() {
+            let _ = writeln!(
+                out,
+                "wtiirn_http_requests_total{{route=\"{}\",status=\"{}\"}} {}",
+                escape(route),
+                status,
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "wtiirn_http_request_duration_seconds",
+            "histogram",
+            "Time taken to answer requests, by route.",
+        );
+        for (route, histogram) in lock(&self.latencies).iter() {
+            let route = escape(route);
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "wtiirn_http_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                    route, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "wtiirn_http_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}\n\
+                 wtiirn_http_request_duration_seconds_sum{{route=\"{}\"}} {}\n\
+                 wtiirn_http_request_duration_seconds_count{{route=\"{}\"}} {}",
+                route, histogram.count, route, histogram.sum, route, histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "wtiirn_no_tide_information_total",
+            "counter",
+            "Pages and API responses with no tide to show.",
+        );
+        let _ = writeln!(
+            out,
+            "wtiirn_no_tide_information_total {}",
+            self.no_tide_information.load(Ordering::SeqCst)
+        );
+
+        let summary = catalogue.summary();
+        gauge(
+            &mut out,
+            "wtiirn_catalogue_stations",
+            "Stations in the catalogue.",
+            Some(summary.stations as i64),
+        );
+        gauge(
+            &mut out,
+            "wtiirn_catalogue_prediction_series",
+            "Stations with predictions in the catalogue.",
+            Some(summary.prediction_series as i64),
+        );
+        gauge(
+            &mut out,
+            "wtiirn_catalogue_earliest_prediction_timestamp_seconds",
+            "Time of the earliest prediction.",
+            summary.earliest.map(|t| t.timestamp()),
+        );
+        gauge(
+            &mut out,
+            "wtiirn_catalogue_latest_prediction_timestamp_seconds",
+            "Time of the latest prediction.",
+            summary.latest.map(|t| t.timestamp()),
+        );
+        gauge(
+            &mut out,
+            "wtiirn_catalogue_first_series_end_timestamp_seconds",
+            "When the first station runs out of predictions.",
+            summary.first_to_run_out.map(|t| t.timestamp()),
+        );
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+}
+
+/// A gauge, left without a sample when there's no value.
+fn gauge(out: &mut String, name: &str, help: &str, value: Option<i64>) {
+    header(out, name, "gauge", help);
+    if let Some(value) = value {
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::StatusCode;
+
+    fn response(route: Option<&str>, status: StatusCode) -> Response<()> {
+        let mut response = Response::builder().status(status).body(()).unwrap();
+        if let Some(route) = route {
+            response
+                .extensions_mut()
+                .insert(MatchedRoute(route.to_string()));
+        }
+        response
+    }
+
+    #[test]
+    fn it_should_count_requests_by_route_and_status() {
+        let metrics = Metrics::new();
+        let mut no_tide = response(Some("/"), StatusCode::OK);
+        no_tide.extensions_mut().insert(NoTideInformation);
+
+        metrics.record(&no_tide, Duration::from_millis(20));
+        metrics.record(
+            &response(Some("/"), StatusCode::OK),
+            Duration::from_millis(200),
+        );
+        metrics.record(
+            &response(None, StatusCode::NOT_FOUND),
+            Duration::from_millis(1),
+        );
+        let out = metrics.render(&StationCatalogue::test());
+
+        for line in &[
+            "wtiirn_http_requests_total{route=\"/\",status=\"200\"} 2",
+            "wtiirn_http_requests_total{route=\"other\",status=\"404\"} 1",
+            "wtiirn_http_request_duration_seconds_bucket{route=\"/\",le=\"0.01\"} 0",
+            "wtiirn_http_request_duration_seconds_bucket{route=\"/\",le=\"0.025\"} 1",
+            "wtiirn_http_request_duration_seconds_bucket{route=\"/\",le=\"0.25\"} 2",
+            "wtiirn_http_request_duration_seconds_bucket{route=\"/\",le=\"+Inf\"} 2",
+            "wtiirn_http_request_duration_seconds_count{route=\"/\"} 2",
+            "wtiirn_no_tide_information_total 1",
+            "wtiirn_catalogue_stations 1",
+            "wtiirn_catalogue_prediction_series 0",
+        ] {
+            assert!(
+                out.lines().any(|l| l == *line),
+                "{} missing from\n{}",
+                line,
+                out
+            );
+        }
+        assert!(out.contains("# TYPE wtiirn_catalogue_latest_prediction_timestamp_seconds gauge"));
+        assert!(!out
+            .lines()
+            .any(|l| l.starts_with("wtiirn_catalogue_latest_prediction_timestamp_seconds")));
+    }
+}
@@ -1,12 +1,14 @@
 use chrono::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use uom::si::f64::*;
 use uom::si::length::meter;
 
+use crate::zone::Zone;
+
 pub static TIME_FORMAT: &str = "%_I:%M %p on %a %b %e, %Y";
 
-#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
 pub struct TidePrediction {
     pub tide: Length,
     pub time: DateTime<FixedOffset>,
@@ -17,8 +19,10 @@ impl TidePrediction {
         self.time < time
     }
 
-    pub fn set_offset(&mut self, offset: FixedOffset) {
-        self.time = self.time.with_timezone(&offset);
+    /// Express this prediction's time in `zone`, using the offset that is
+    /// in effect at that particular instant.
+    pub fn set_zone(&mut self, zone: &Zone) {
+        self.time = zone.localize(&self.time);
     }
 
     pub fn as_table_row(&self) -> String {
@@ -70,7 +74,7 @@ impl TidePredictionPair {
         }
     }
 
-    fn prev_tide_type(&self) -> &str {
+    pub fn prev_tide_type(&self) -> &str {
         if self.tide_is_coming_in() {
             "Low"
         } else {
@@ -78,7 +82,7 @@ impl TidePredictionPair {
         }
     }
 
-    fn next_tide_type(&self) -> &str {
+    pub fn next_tide_type(&self) -> &str {
         if !self.tide_is_coming_in() {
             "Low"
         } else {
@@ -102,9 +106,9 @@ impl TidePredictionPair {
             )
     }
 
-    pub fn set_offset(&mut self, offset: FixedOffset) -> Self {
-        self.next.set_offset(offset);
-        self.prev.set_offset(offset);
+    pub fn set_zone(&mut self, zone: &Zone) -> Self {
+        self.next.set_zone(zone);
+        self.prev.set_zone(zone);
         *self
     }
 
@@ -113,7 +117,7 @@ impl TidePredictionPair {
     }
 }
 
-#[derive(Debug, PartialEq, Copy, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Copy, Clone, Deserialize, Serialize)]
 pub struct Coordinates {
     pub lat: f64,
     pub lon: f64,
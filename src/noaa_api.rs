@@ -1,4 +1,10 @@
 use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::stations::report::LoadReport;
+use crate::stations::{PredictionsWithId, Station};
 
 pub mod transform;
 
@@ -30,6 +36,8 @@ pub struct HighLowAndMetadata {
     station_name: String,
     latitude: f64,
     longitude: f64,
+    /// Two letter code of the US state or territory the station is in.
+    state: Option<String>,
     #[serde(rename = "timeZone")]
     time_zone: String,
     #[serde(rename = "unit")]
@@ -37,3 +45,158 @@ pub struct HighLowAndMetadata {
     #[serde(rename = "HighLowValues")]
     values: HighLowValues,
 }
+
+pub fn parse_high_low(src: &str) -> Result<HighLowAndMetadata, serde_xml_rs::Error> {
+    serde_xml_rs::from_str(src)
+}
+
+/// Read every NOAA high/low XML file (`*.xml`) in a directory, producing
+/// the stations they describe along with their predictions. Files that
+/// can't be read, parsed or converted are skipped and noted in `report`.
+///
+/// Files with times in local time need the station's time zone. When the
+/// file doesn't name one it's taken from the matching station in `known`,
+/// then from the state the station is in.
+pub fn load_from_dir(
+    path: &Path,
+    known: &[Station],
+    report: &mut LoadReport,
+) -> Result<(Vec<Station>, Vec<PredictionsWithId>), Box<dyn Error>> {
+    let mut stations = vec![];
+    let mut predictions = vec![];
+    for file in fs::read_dir(path)? {
+        let path = file?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("xml") {
+            continue;
+        }
+        let file = report.file(&path);
+        match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|src| parse_high_low(&src).map_err(|e| e.to_string()))
+            .and_then(|metadata| load_file(&metadata, known).map_err(|e| e.to_string()))
+        {
+            Ok((station, station_predictions)) => {
+                file.loaded += station_predictions.len();
+                stations.push(station);
+                predictions.extend(station_predictions);
+            }
+            Err(e) => file.skip(None, e),
+        }
+    }
+    Ok((stations, predictions))
+}
+
+fn load_file(
+    metadata: &HighLowAndMetadata,
+    known: &[Station],
+) -> Result<(Station, Vec<PredictionsWithId>), transform::TransformError> {
+    let mut station = transform::extract_station(metadata);
+    let id = station.id;
+    station.time_zone = station
+        .time_zone
+        .or_else(|| {
+            known
+                .iter()
+                .find(|s| s.id == id)
+                .and_then(|s| s.time_zone.clone())
+        })
+        .or_else(|| transform::state_time_zone(metadata));
+    let predictions =
+        transform::extract_predictions_in_zone(metadata, station.named_zone().as_ref())?;
+    Ok((station, predictions))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use transform::TransformError;
+
+    #[test]
+    fn it_should_load_stations_and_predictions_from_xml() {
+        let (stations, predictions) = load_from_dir(
+            Path::new("test_data/noaa/good"),
+            &[],
+            &mut LoadReport::default(),
+        )
+        .unwrap();
+
+        assert_eq!(stations.len(), 1);
+        assert_eq!(stations[0].name, "San Francisco");
+        assert_eq!(stations[0].coordinates.lat, 37.8063);
+        assert_eq!(stations[0].coordinates.lon, -122.4659);
+        assert_eq!(
+            stations[0].id,
+            Station::generate_id("San Francisco", 9414290)
+        );
+        assert!(predictions.iter().all(|p| p.station_id == stations[0].id));
+        let count: usize = predictions.iter().map(|p| p.predictions.len()).sum();
+        assert_eq!(count, 8);
+    }
+
+    #[test]
+    fn it_should_read_local_times_in_the_station_zone() {
+        use chrono::prelude::*;
+        let mut report = LoadReport::default();
+        let (stations, predictions) =
+            load_from_dir(Path::new("test_data/noaa/local"), &[], &mut report).unwrap();
+        let times = |name| {
+            let station = stations.iter().find(|s| s.name == name).unwrap();
+            let times: Vec<_> = predictions
+                .iter()
+                .filter(|p| p.station_id == station.id)
+                .flat_map(|p| p.predictions.iter().map(|p| p.time))
+                .collect();
+            (station.time_zone.clone(), times)
+        };
+
+        // LST/LDT in the zone of the station's state, across the switch
+        // to daylight saving time.
+        let (zone, seattle) = times("Seattle");
+        assert_eq!(zone, Some("America/Los_Angeles".to_string()));
+        assert_eq!(seattle[0], Utc.ymd(2019, 3, 9).and_hms(14, 12, 0));
+        assert_eq!(seattle[3], Utc.ymd(2019, 3, 10).and_hms(9, 2, 0));
+        assert_eq!(seattle[4], Utc.ymd(2019, 3, 10).and_hms(14, 49, 0));
+
+        // LST without a zone or a state can't be read, rather than being
+        // read in a zone guessed from longitude.
+        assert!(stations.iter().all(|s| s.name != "Apra Harbor, Guam"));
+        let skipped: Vec<_> = report
+            .files
+            .iter()
+            .flat_map(|f| f.skipped.iter().map(|issue| issue.reason.clone()))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![TransformError::LocalTimeWithoutZone("LST".to_string()).to_string()]
+        );
+
+        let known = Station {
+            time_zone: Some("America/Vancouver".to_string()),
+            ..stations
+                .iter()
+                .find(|s| s.name == "Seattle")
+                .unwrap()
+                .clone()
+        };
+        let (stations, _) = load_from_dir(
+            Path::new("test_data/noaa/local"),
+            &[known],
+            &mut LoadReport::default(),
+        )
+        .unwrap();
+        assert!(stations
+            .iter()
+            .any(|s| s.time_zone == Some("America/Vancouver".to_string())));
+    }
+
+    #[test]
+    fn it_should_skip_broken_and_unrelated_files() {
+        let mut report = LoadReport::default();
+        let (stations, _) =
+            load_from_dir(Path::new("test_data/noaa/broken"), &[], &mut report).unwrap();
+
+        assert_eq!(stations.len(), 1);
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.skipped(), 1);
+    }
+}
@@ -1,13 +1,141 @@
 use chrono::prelude::*;
+use std::error::Error;
+use std::fmt;
 use uom::si::f64::*;
-use uom::si::length::foot;
+use uom::si::length::{foot, meter};
 
-use crate::model::TidePrediction;
+use crate::model::{Coordinates, TidePrediction};
 use crate::noaa_api::HighLowAndMetadata;
 use crate::stations::{PredictionsWithId, Station};
+use crate::zone::Zone;
 
-pub fn extract_predictions(m: &HighLowAndMetadata) -> Vec<PredictionsWithId> {
+/// Reasons a NOAA file can't be turned into predictions.
+#[derive(Debug, PartialEq, Clone)]
+pub enum TransformError {
+    UnknownUnit(String),
+    UnknownTimeZone(String),
+    /// Times are in the station's local time, but we don't know which
+    /// zone the station is in.
+    LocalTimeWithoutZone(String),
+    InvalidDateTime {
+        date: String,
+        time: String,
+    },
+    /// A local time that was skipped by a daylight saving transition.
+    NonexistentLocalTime {
+        date: String,
+        time: String,
+    },
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransformError::UnknownUnit(unit) => write!(f, "unknown unit {:?}", unit),
+            TransformError::UnknownTimeZone(tz) => write!(f, "unknown time zone {:?}", tz),
+            TransformError::LocalTimeWithoutZone(tz) => write!(
+                f,
+                "times are in {}, but the station's IANA time zone isn't known",
+                tz
+            ),
+            TransformError::InvalidDateTime { date, time } => {
+                write!(f, "couldn't parse date {:?} and time {:?}", date, time)
+            }
+            TransformError::NonexistentLocalTime { date, time } => write!(
+                f,
+                "{} {} doesn't exist in the station's time zone",
+                date, time
+            ),
+        }
+    }
+}
+
+impl Error for TransformError {}
+
+/// The time zones NOAA declares prediction times to be in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum DeclaredZone {
+    /// GMT/UTC.
+    Utc,
+    /// Local standard time all year round.
+    Standard(FixedOffset),
+    /// Local standard or daylight time, whichever is in effect.
+    Local(Zone),
+}
+
+/// Build the station described by a NOAA file's metadata.
+///
+/// NOAA mostly declares `GMT` or `LST/LDT` rather than a zone name. Those
+/// only describe how the prediction times are written, so the station's
+/// own zone is only kept when the file names an actual IANA zone.
+pub fn extract_station(m: &HighLowAndMetadata) -> Station {
+    let time_zone = match m.time_zone.as_str() {
+        "GMT" | "LST" | "LST/LDT" => None,
+        name => Zone::from_name(name).map(|_| name.to_string()),
+    };
+    Station {
+        name: m.station_name.clone(),
+        coordinates: Coordinates {
+            lat: m.latitude,
+            lon: m.longitude,
+        },
+        id: Station::generate_id(&m.station_name, m.station_id),
+        time_zone,
+    }
+}
+
+/// The IANA zone of the US state or territory a NOAA file says its station
+/// is in. States that span zones are split by longitude where they meet
+/// the coast.
+pub fn state_time_zone(m: &HighLowAndMetadata) -> Option<String> {
+    let name = match m.state.as_ref()?.trim() {
+        "ME" | "NH" | "MA" | "RI" | "CT" | "NY" | "NJ" | "PA" | "DE" | "MD" | "DC" | "VA"
+        | "NC" | "SC" | "GA" | "OH" => "America/New_York",
+        "FL" if m.longitude < -85.0 => "America/Chicago",
+        "FL" => "America/New_York",
+        "MI" => "America/Detroit",
+        "AL" | "MS" | "LA" | "TX" | "IN" | "IL" | "WI" | "MN" => "America/Chicago",
+        "CA" | "OR" | "WA" => "America/Los_Angeles",
+        "AK" if m.longitude > 0.0 || m.longitude < -169.5 => "America/Adak",
+        "AK" => "America/Anchorage",
+        "HI" => "Pacific/Honolulu",
+        "PR" => "America/Puerto_Rico",
+        "VI" => "America/St_Thomas",
+        "GU" => "Pacific/Guam",
+        "MP" => "Pacific/Saipan",
+        "AS" => "Pacific/Pago_Pago",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// Convert a NOAA file's predictions, using the zone named in the file or
+/// implied by its state to interpret local times.
+pub fn extract_predictions(
+    m: &HighLowAndMetadata,
+) -> Result<Vec<PredictionsWithId>, TransformError> {
+    let mut station = extract_station(m);
+    station.time_zone = station.time_zone.or_else(|| state_time_zone(m));
+    extract_predictions_in_zone(m, station.named_zone().as_ref())
+}
+
+/// Convert a NOAA file's predictions, honouring its declared unit and
+/// time zone. `station_zone` is needed to interpret `LST` and `LST/LDT`
+/// times, and must be a named zone for `LST/LDT`.
+pub fn extract_predictions_in_zone(
+    m: &HighLowAndMetadata,
+    station_zone: Option<&Zone>,
+) -> Result<Vec<PredictionsWithId>, TransformError> {
     let station_id = Station::generate_id(&m.station_name, m.station_id);
+    let to_length = length_unit(&m.unit_name)?;
+    let year = m
+        .values
+        .values
+        .first()
+        .and_then(|item| NaiveDate::parse_from_str(&item.date, "%m/%d/%Y").ok())
+        .map_or(2000, |date| date.year());
+    let zone = declared_zone(&m.time_zone, station_zone, year)?;
+
     m.values
         .values
         .iter()
@@ -15,44 +143,109 @@ pub fn extract_predictions(m: &HighLowAndMetadata) -> Vec<PredictionsWithId> {
             let predictions = item
                 .data
                 .iter()
-                .map(move |data| TidePrediction {
-                    tide: Length::new::<foot>(data.pred.into()),
-                    time: parse_date_time(&item.date, &data.time),
+                .map(|data| {
+                    Ok(TidePrediction {
+                        tide: to_length(data.pred.into()),
+                        time: parse_date_time(&item.date, &data.time, &zone)?,
+                    })
                 })
-                .collect();
-            PredictionsWithId {
+                .collect::<Result<_, _>>()?;
+            Ok(PredictionsWithId {
                 station_id,
                 predictions,
-            }
+            })
         })
         .collect()
 }
 
-fn parse_date_time(date: &str, time: &str) -> DateTime<FixedOffset> {
-    let time_str = format!("{}/{}:00+0000", date, time);
+/// How to build lengths in a unit named in a file, e.g. `Feet` or `m`.
+pub(crate) fn length_unit(unit: &str) -> Result<fn(f64) -> Length, TransformError> {
+    match unit.trim().to_lowercase().as_str() {
+        "feet" | "foot" | "ft" => Ok(Length::new::<foot>),
+        "meters" | "meter" | "metres" | "metre" | "m" => Ok(Length::new::<meter>),
+        _ => Err(TransformError::UnknownUnit(unit.to_string())),
+    }
+}
+
+/// Interpret a file's declared time zone. `year` picks which year's
+/// standard offset `LST` refers to.
+pub(crate) fn declared_zone(
+    time_zone: &str,
+    station_zone: Option<&Zone>,
+    year: i32,
+) -> Result<DeclaredZone, TransformError> {
+    match (time_zone.trim(), station_zone) {
+        ("GMT", _) | ("UTC", _) => Ok(DeclaredZone::Utc),
+        ("LST", Some(zone)) => Ok(DeclaredZone::Standard(standard_offset(zone, year))),
+        ("LST/LDT", Some(zone @ Zone::Named(_))) => Ok(DeclaredZone::Local(*zone)),
+        ("LST", None) | ("LST/LDT", _) => {
+            Err(TransformError::LocalTimeWithoutZone(time_zone.to_string()))
+        }
+        (name, _) => Zone::from_name(name)
+            .map(DeclaredZone::Local)
+            .ok_or_else(|| TransformError::UnknownTimeZone(name.to_string())),
+    }
+}
+
+/// The offset a zone uses outside of daylight saving time: whichever of
+/// its mid-winter and mid-summer offsets is further west.
+fn standard_offset(zone: &Zone, year: i32) -> FixedOffset {
+    let january = zone.offset_at(&Utc.ymd(year, 1, 15).and_hms(0, 0, 0));
+    let july = zone.offset_at(&Utc.ymd(year, 7, 15).and_hms(0, 0, 0));
+    if january.local_minus_utc() <= july.local_minus_utc() {
+        january
+    } else {
+        july
+    }
+}
+
+fn parse_date_time(
+    date: &str,
+    time: &str,
+    zone: &DeclaredZone,
+) -> Result<DateTime<FixedOffset>, TransformError> {
+    let naive = NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%m/%d/%Y %H:%M")
+        .map_err(|_| TransformError::InvalidDateTime {
+            date: date.to_string(),
+            time: time.to_string(),
+        })?;
+    localize(&naive, zone).ok_or_else(|| TransformError::NonexistentLocalTime {
+        date: date.to_string(),
+        time: time.to_string(),
+    })
+}
 
-    DateTime::parse_from_str(&time_str, "%m/%d/%Y/%H:%M:%S%z").expect("couldn't parse date")
+/// Attach the declared zone's offset to a time read from a file, or `None`
+/// if that local time was skipped by a daylight saving transition.
+pub(crate) fn localize(
+    naive: &NaiveDateTime,
+    zone: &DeclaredZone,
+) -> Option<DateTime<FixedOffset>> {
+    let offset = match zone {
+        DeclaredZone::Utc => FixedOffset::east(0),
+        DeclaredZone::Standard(offset) => *offset,
+        DeclaredZone::Local(Zone::Fixed(offset)) => *offset,
+        // During the hour that's repeated when DST ends, take the first.
+        DeclaredZone::Local(Zone::Named(tz)) => {
+            tz.offset_from_local_datetime(naive).earliest()?.fix()
+        }
+    };
+    offset.from_local_datetime(naive).single()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    #[test]
-    fn it_should_parse_the_date() {
-        let time = parse_date_time("01/27/2009", "01:23");
-        let utc = FixedOffset::west(0);
-        assert_eq!(utc.ymd(2009, 01, 27).and_hms(01, 23, 0), time);
-    }
 
-    #[test]
-    fn it_should_extract_the_predictions() {
+    fn metadata(time_zone: &str) -> HighLowAndMetadata {
         use crate::noaa_api::{HighLowValues, Item, TideData};
-        let m = HighLowAndMetadata {
+        HighLowAndMetadata {
             station_id: 1000,
             station_name: "fake station".to_string(),
             latitude: 0.0,
             longitude: 0.0,
-            time_zone: "UTC".to_string(),
+            state: None,
+            time_zone: time_zone.to_string(),
             unit_name: "foot".to_string(),
             values: HighLowValues {
                 values: vec![Item {
@@ -63,9 +256,140 @@ mod test {
                     }],
                 }],
             },
-        };
+        }
+    }
+
+    #[test]
+    fn it_should_extract_the_station() {
+        let station = extract_station(&metadata("America/Anchorage"));
+
+        assert_eq!(station.name, "fake station");
+        assert_eq!(station.id, Station::generate_id("fake station", 1000));
+        assert_eq!(station.time_zone, Some("America/Anchorage".to_string()));
+        assert_eq!(extract_station(&metadata("LST/LDT")).time_zone, None);
+    }
+    #[test]
+    fn it_should_parse_the_date() {
+        let time = parse_date_time("01/27/2009", "01:23", &DeclaredZone::Utc).unwrap();
+        let utc = FixedOffset::west(0);
+        assert_eq!(utc.ymd(2009, 01, 27).and_hms(01, 23, 0), time);
+    }
+
+    #[test]
+    fn it_should_reject_bad_dates_instead_of_panicking() {
+        assert_eq!(
+            parse_date_time("27/01/2009", "01:23", &DeclaredZone::Utc),
+            Err(TransformError::InvalidDateTime {
+                date: "27/01/2009".to_string(),
+                time: "01:23".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn it_should_honour_the_declared_unit() {
+        let mut m = metadata("GMT");
+        m.unit_name = "Meters".to_string();
+        let extracted = extract_predictions(&m).unwrap();
+        assert_eq!(extracted[0].predictions[0].tide, Length::new::<meter>(1.0));
+
+        m.unit_name = "fathoms".to_string();
+        assert_eq!(
+            extract_predictions(&m),
+            Err(TransformError::UnknownUnit("fathoms".to_string()))
+        );
+    }
+
+    #[test]
+    fn it_should_honour_the_declared_time_zone() {
+        let vancouver = Zone::from_name("America/Vancouver").unwrap();
+        let mut m = metadata("LST/LDT");
+        m.values.values[0].date = "07/01/2019".to_string();
+
+        let summer = extract_predictions_in_zone(&m, Some(&vancouver)).unwrap();
+        assert_eq!(
+            summer[0].predictions[0].time,
+            FixedOffset::west(7 * 3600)
+                .ymd(2019, 7, 1)
+                .and_hms(12, 0, 0)
+        );
+
+        m.time_zone = "LST".to_string();
+        let standard = extract_predictions_in_zone(&m, Some(&vancouver)).unwrap();
+        assert_eq!(
+            standard[0].predictions[0].time,
+            FixedOffset::west(8 * 3600)
+                .ymd(2019, 7, 1)
+                .and_hms(12, 0, 0)
+        );
+    }
+
+    #[test]
+    fn it_should_refuse_local_times_without_a_station_zone() {
+        let m = metadata("LST/LDT");
+        assert_eq!(
+            extract_predictions(&m),
+            Err(TransformError::LocalTimeWithoutZone("LST/LDT".to_string()))
+        );
+        let fixed = Zone::Fixed(FixedOffset::west(8 * 3600));
+        assert!(extract_predictions_in_zone(&m, Some(&fixed)).is_err());
+
+        let m = metadata("Moon Standard Time");
+        assert_eq!(
+            extract_predictions(&m),
+            Err(TransformError::UnknownTimeZone(
+                "Moon Standard Time".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn it_should_find_the_zone_of_the_station_state() {
+        let mut m = metadata("LST/LDT");
+        assert_eq!(state_time_zone(&m), None);
+
+        m.state = Some("TX".to_string());
+        assert_eq!(state_time_zone(&m), Some("America/Chicago".to_string()));
+        let extracted = extract_predictions(&m).unwrap();
+        assert_eq!(
+            extracted[0].predictions[0].time,
+            FixedOffset::west(6 * 3600)
+                .ymd(2019, 1, 1)
+                .and_hms(12, 0, 0)
+        );
+
+        m.state = Some("FL".to_string());
+        m.longitude = -80.2;
+        assert_eq!(state_time_zone(&m), Some("America/New_York".to_string()));
+        m.longitude = -87.2;
+        assert_eq!(state_time_zone(&m), Some("America/Chicago".to_string()));
+    }
+
+    #[test]
+    fn it_should_not_guess_the_zone_for_standard_time() {
+        let mut m = metadata("LST");
+        m.longitude = -122.0;
+
+        assert_eq!(
+            extract_predictions(&m),
+            Err(TransformError::LocalTimeWithoutZone("LST".to_string()))
+        );
+
+        m.state = Some("WA".to_string());
+        let extracted = extract_predictions(&m).unwrap();
+        assert_eq!(
+            extracted[0].predictions[0].time,
+            FixedOffset::west(8 * 3600)
+                .ymd(2019, 1, 1)
+                .and_hms(12, 0, 0)
+        );
+    }
+
+    #[test]
+    fn it_should_extract_the_predictions() {
+        let m = metadata("UTC");
 
-        let extracted = extract_predictions(&m);
+        let extracted = extract_predictions(&m).unwrap();
 
         assert_eq!(extracted.len(), 1);
         assert_eq!(extracted[0].predictions[0].tide, Length::new::<foot>(1.0));
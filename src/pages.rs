@@ -4,21 +4,46 @@ use serde::Deserialize;
 use uom::si::f64::*;
 use uom::si::length::{centimeter, kilometer, meter};
 
+use crate::chart;
 use crate::compute;
-use crate::model::{Coordinates, TidePredictionPair, TIME_FORMAT};
-use crate::stations::{Station, StationCatalogue};
+use crate::model::{Coordinates, TidePrediction, TidePredictionPair, TIME_FORMAT};
+use crate::stations::{Station, StationCatalogue, Substitution};
+use crate::zone::Zone;
+
+/// How many alternatives to the chosen station to list on the home page.
+const NEARBY_STATIONS: usize = 5;
 
 static POINT_ATKINSON: Coordinates = Coordinates {
     lat: 49.3299,
     lon: -123.2650,
 };
 
-#[derive(Deserialize, Clone, Copy, Debug)]
+/// What pages show when a request doesn't say.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Defaults {
+    /// Where to look for the nearest station without a location.
+    pub location: Coordinates,
+    /// The zone to show times in without one from the viewer. When this
+    /// isn't set either, times are in the station's own zone.
+    pub zone: Option<Zone>,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Defaults {
+            location: POINT_ATKINSON,
+            zone: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
 pub struct HomePageParams {
     lat: Option<f64>,
     lon: Option<f64>,
     #[serde(alias = "offset")]
     offset_in_minutes: Option<i32>,
+    tz: Option<String>,
 }
 
 impl HomePageParams {
@@ -28,38 +53,121 @@ impl HomePageParams {
             _ => None,
         }
     }
+
+    fn get_zone(&self) -> Option<Zone> {
+        requested_zone(&self.tz, self.offset_in_minutes)
+    }
+}
+
+/// The zone the viewer asked for: a named IANA zone if it's valid,
+/// otherwise their fixed UTC offset.
+fn requested_zone(tz: &Option<String>, offset_in_minutes: Option<i32>) -> Option<Zone> {
+    tz.as_ref()
+        .and_then(|name| Zone::from_name(name))
+        .or_else(|| offset_in_minutes.and_then(Zone::from_minutes_west))
+}
+
+/// The query parameter that asks for `zone` again, in a form
+/// `requested_zone` understands: fixed offsets as minutes west of UTC,
+/// names percent-encoded.
+fn zone_query(zone: &Zone) -> String {
+    match zone {
+        Zone::Named(_) => serde_urlencoded::to_string(&[("tz", zone.name())]).unwrap_or_default(),
+        Zone::Fixed(offset) => format!("offset={}", -offset.local_minus_utc() / 60),
+    }
 }
 
 pub struct HomePageViewModel {
-    current_time: DateTime<FixedOffset>,
-    current_location: Option<Coordinates>,
-    prediction_pair: Option<TidePredictionPair>,
-    station: Station,
+    pub(crate) current_time: DateTime<FixedOffset>,
+    pub(crate) zone: Zone,
+    pub(crate) station_zone: Zone,
+    pub(crate) current_location: Option<Coordinates>,
+    pub(crate) prediction_pair: Option<TidePredictionPair>,
+    pub(crate) predictions: Vec<TidePrediction>,
+    pub(crate) station: Station,
+    pub(crate) substitution: Option<Substitution>,
+    pub(crate) nearby: Vec<(Station, Length)>,
 }
 
 impl HomePageViewModel {
     /// Collect the information necessary for rendering the home page based on a request's
     /// location and the station catalogue that was loaded at startup.
-    pub fn new(stn_catalogue: &StationCatalogue, params: &Option<HomePageParams>) -> Self {
-        let offset_in_minutes = params.and_then(|x| x.offset_in_minutes).unwrap_or(8 * 60);
-        let offset = FixedOffset::west(offset_in_minutes * 60);
-        let current_time = Local::now().with_timezone(&offset);
-
-        let coords = params.and_then(|x| x.get_coords());
-        let station = stn_catalogue.find_near(&coords.unwrap_or_else(|| POINT_ATKINSON));
-        let predictions = stn_catalogue.predictions_for_station(&station);
-        let prediction_pair = predictions
-            .and_then(|preds| compute::find::nearest_pair(&preds, current_time))
-            .map(|mut x| x.set_offset(offset));
+    /// Times are shown in the zone from the request, falling back to the
+    /// default zone and then the station's own zone.
+    pub fn new(
+        stn_catalogue: &StationCatalogue,
+        params: &Option<HomePageParams>,
+        defaults: &Defaults,
+    ) -> Self {
+        let coords = params.as_ref().and_then(|x| x.get_coords());
+        let (station, substitution) = stn_catalogue.find_near_with_predictions(
+            &coords.unwrap_or(defaults.location),
+            Utc::now().with_timezone(&FixedOffset::east(0)),
+        );
+        let mut vm = Self::for_station(stn_catalogue, station, params, defaults);
+        vm.substitution = substitution;
+        vm
+    }
+
+    /// Like `new`, but for a station that was chosen explicitly rather than
+    /// the one nearest to the request's location.
+    pub fn for_station(
+        stn_catalogue: &StationCatalogue,
+        station: &Station,
+        params: &Option<HomePageParams>,
+        defaults: &Defaults,
+    ) -> Self {
+        let coords = params.as_ref().and_then(|x| x.get_coords());
+        let station_zone = station.zone();
+        let zone = params
+            .as_ref()
+            .and_then(|x| x.get_zone())
+            .or(defaults.zone)
+            .unwrap_or(station_zone);
+        let current_time = zone.localize(&Utc::now());
+
+        let prediction_pair = stn_catalogue
+            .pair_for_station(station, current_time)
+            .map(|mut x| x.set_zone(&zone));
+        let predictions = stn_catalogue
+            .predictions_covering(
+                station,
+                current_time - chrono::Duration::days(1),
+                current_time + chrono::Duration::days(2),
+            )
+            .to_vec();
+
+        let nearby = stn_catalogue
+            .find_k_nearest(&coords.unwrap_or(station.coordinates), NEARBY_STATIONS + 1)
+            .into_iter()
+            .filter(|(s, _)| s.id != station.id)
+            .take(NEARBY_STATIONS)
+            .map(|(s, distance)| (s.clone(), distance))
+            .collect();
 
         HomePageViewModel {
             current_time,
+            zone,
+            station_zone,
             current_location: coords,
             prediction_pair,
+            predictions,
             station: station.clone(),
+            substitution: None,
+            nearby,
         }
     }
 
+    /// The station the page is about.
+    pub fn station(&self) -> &Station {
+        &self.station
+    }
+
+    /// False when there's no prediction before and after the current time.
+    pub fn has_tide_information(&self) -> bool {
+        self.prediction_pair.is_some()
+    }
+
     fn headline(&self) -> String {
         match self.prediction_pair {
             Some(p) => p.headline(),
@@ -69,12 +177,43 @@ impl HomePageViewModel {
 
     fn detail(&self) -> String {
         match self.prediction_pair {
-            Some(p) => p.as_table(),
+            Some(p) => p.as_table() + &self.station_local_times(),
             _ => "".into(),
         }
     }
 
-    fn distance_from_station(&self) -> Length {
+    /// Mention the times at the station as well, but only when they'd read
+    /// differently from the viewer's own.
+    fn station_local_times(&self) -> String {
+        let pair = match self.prediction_pair {
+            Some(p) if self.differs_from_station_zone(&p) => p,
+            _ => return "".into(),
+        };
+        format!(
+            "<p class='station-time'>At the station ({}) the previous tide was at {} and the next tide is at {}</p>",
+            self.station_zone.name(),
+            self.station_zone.localize(&pair.prev.time).format(TIME_FORMAT),
+            self.station_zone.localize(&pair.next.time).format(TIME_FORMAT),
+        )
+    }
+
+    fn differs_from_station_zone(&self, pair: &TidePredictionPair) -> bool {
+        [pair.prev.time, pair.next.time]
+            .iter()
+            .any(|t| self.zone.offset_at(t) != self.station_zone.offset_at(t))
+    }
+
+    fn chart(&self) -> String {
+        chart::tide_chart(
+            &self.predictions,
+            self.current_time,
+            &self.zone,
+            &self.station.coordinates,
+        )
+        .unwrap_or_default()
+    }
+
+    pub(crate) fn distance_from_station(&self) -> Length {
         match self.current_location {
             None => Length::new::<meter>(0.0),
             Some(c) => compute::gcd::great_circle_distance(&c, &self.station.coordinates),
@@ -93,6 +232,12 @@ impl HomePageViewModel {
                 self.km_from_station()
             );
         }
+        if let Some(substitution) = &self.substitution {
+            info += &format!(
+                ". The nearest station, <b>{}</b>, {}",
+                substitution.nearest.name, substitution.reason
+            );
+        }
         info
     }
 
@@ -121,6 +266,52 @@ impl HomePageViewModel {
         }
     }
 
+    /// Links to other stations close by, in case the nearest one isn't
+    /// the most useful.
+    fn nearby_stations(&self) -> String {
+        if self.nearby.is_empty() {
+            return "".into();
+        }
+        let from = if self.current_location.is_some() {
+            "from you"
+        } else {
+            "from this station"
+        };
+        let location = self
+            .current_location
+            .map(|c| format!("lat={}&lon={}&", c.lat, c.lon))
+            .unwrap_or_default();
+        let items: String = self
+            .nearby
+            .iter()
+            .map(|(station, distance)| {
+                format!(
+                    "<li><a href='/station/{}?{}{}'>{}</a> ({:.1} KM {})</li>",
+                    station.id,
+                    location,
+                    zone_query(&self.zone),
+                    station.name,
+                    distance.get::<kilometer>(),
+                    from
+                )
+            })
+            .collect();
+        format!(
+            "<div class='nearby'><h3>Nearby stations</h3><ul>{}</ul></div>",
+            items
+        )
+    }
+
+    fn station_links(&self) -> String {
+        format!(
+            "<p><a href='/station/{id}/table?days={days}&{zone}'>Tide table for the next {days} days</a>
+            | <a href='/station/{id}'>Link to this station</a></p>",
+            id = self.station.id,
+            days = DEFAULT_TABLE_DAYS,
+            zone = zone_query(&self.zone),
+        )
+    }
+
     fn station_lat(&self) -> f64 {
         self.station.coordinates.lat
     }
@@ -135,7 +326,7 @@ pub fn home_page(vm: HomePageViewModel) -> String {
         r#"<html>
             <head>
                 <title>What Tide Is It Right Now?!</title>
-                <link REL=stylesheet href='style.css' />
+                <link REL=stylesheet href='/style.css' />
 
                 <link rel='stylesheet' href='https://unpkg.com/leaflet@1.5.1/dist/leaflet.css'
                   integrity='sha512-xwE/Az9zrjBIphAcBb3F6JVqxf46+CDLwfLMHloNu6KEQCAWi6HcDUbeOfBIptF7tcCzusKFjFw2yuvEpDL9wQ=='
@@ -163,11 +354,16 @@ pub fn home_page(vm: HomePageViewModel) -> String {
                         <div class='detail'>
                             {}
                             <p>{}</p>
+                            {}
+                        </div>
+                        <div class='chart'>
+                            {}
                         </div>
+                        {}
                         <div id='map'></div>
                     </div>
                 </div>
-                <script src='getlocation.js'></script>
+                <script src='/getlocation.js'></script>
                 <script>
                   showMap({}, {})
                 </script>
@@ -178,11 +374,142 @@ pub fn home_page(vm: HomePageViewModel) -> String {
         vm.current_level(),
         vm.detail(),
         vm.station_info(),
+        vm.station_links(),
+        vm.chart(),
+        vm.nearby_stations(),
         vm.station_lat(),
         vm.station_lon(),
     )
 }
 
+const DEFAULT_TABLE_DAYS: u32 = 7;
+const MAX_TABLE_DAYS: u32 = 31;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TablePageParams {
+    days: Option<u32>,
+    #[serde(alias = "offset")]
+    offset_in_minutes: Option<i32>,
+    tz: Option<String>,
+}
+
+pub struct TablePageViewModel {
+    station: Station,
+    zone: Zone,
+    days: Vec<(NaiveDate, Vec<(bool, TidePrediction)>)>,
+}
+
+impl TablePageViewModel {
+    /// Collect every high and low tide for a station from the start of today
+    /// until the end of the requested number of days, grouped by local date.
+    pub fn new(
+        station: &Station,
+        stn_catalogue: &StationCatalogue,
+        params: &Option<TablePageParams>,
+        defaults: &Defaults,
+    ) -> Self {
+        let day_count = params
+            .as_ref()
+            .and_then(|x| x.days)
+            .unwrap_or(DEFAULT_TABLE_DAYS)
+            .clamp(1, MAX_TABLE_DAYS);
+        let zone = params
+            .as_ref()
+            .and_then(|x| requested_zone(&x.tz, x.offset_in_minutes))
+            .or(defaults.zone)
+            .unwrap_or_else(|| station.zone());
+        let today = zone.localize(&Utc::now()).naive_local().date();
+        let last_day = today + chrono::Duration::days(i64::from(day_count) - 1);
+
+        let predictions = stn_catalogue
+            .predictions_for_station(station)
+            .unwrap_or(&[]);
+
+        let mut days: Vec<(NaiveDate, Vec<(bool, TidePrediction)>)> = vec![];
+        for (i, prediction) in predictions.iter().enumerate() {
+            let mut local = *prediction;
+            local.set_zone(&zone);
+            let date = local.time.naive_local().date();
+            if date < today || date > last_day {
+                continue;
+            }
+            let row = (compute::find::is_high_tide(predictions, i), local);
+            match days.last_mut() {
+                Some((d, rows)) if *d == date => rows.push(row),
+                _ => days.push((date, vec![row])),
+            }
+        }
+
+        TablePageViewModel {
+            station: station.clone(),
+            zone,
+            days,
+        }
+    }
+
+    fn day_tables(&self) -> String {
+        if self.days.is_empty() {
+            return "<p>No tide information for these days.</p>".into();
+        }
+        self.days
+            .iter()
+            .map(|(date, rows)| {
+                let rows: String = rows
+                    .iter()
+                    .map(|(is_high, p)| {
+                        format!(
+                            "<tr><td>{}</td><td>{:.2}m</td><td>{}</td></tr>",
+                            if *is_high { "High" } else { "Low" },
+                            p.tide.get::<meter>(),
+                            p.time.format("%_I:%M %p")
+                        )
+                    })
+                    .collect();
+                format!(
+                    "<h3>{}</h3>
+                    <table>
+                    <thead>
+                    <th>Tide</th><th><a target='_blank' href='https://en.wikipedia.org/wiki/Chart_datum'>Level</a></th><th>Time</th>
+                    </thead>
+                    {}
+                    </table>",
+                    date.format("%A %B %e, %Y"),
+                    rows
+                )
+            })
+            .collect()
+    }
+}
+
+pub fn table_page(vm: TablePageViewModel) -> String {
+    format!(
+        r#"<html>
+            <head>
+                <title>Tide Table for {} - What Tide Is It Right Now?!</title>
+                <link REL=stylesheet href='/style.css' />
+            </head>
+            <body>
+                <div class='container'>
+                    <div class='content'>
+                        <div class='title'>
+                            <h1>Tide Table for {}</h1>
+                        </div>
+                        <p>All times are in {}</p>
+                        <div class='detail'>
+                            {}
+                        </div>
+                        <p><a href='/'>What tide is it right now?</a></p>
+                    </div>
+                </div>
+            </body>
+        </html>"#,
+        vm.station.name,
+        vm.station.name,
+        vm.zone.name(),
+        vm.day_tables(),
+    )
+}
+
 pub fn not_found_page() -> String {
     "<html><h1>404</h1><p>Not found!<p></html>".to_string()
 }
@@ -0,0 +1,162 @@
+use log::{info, warn};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::stations::StationCatalogue;
+
+/// A catalogue that can be replaced while requests are using it. Clones
+/// share the same catalogue.
+#[derive(Clone)]
+pub struct SharedCatalogue {
+    current: Arc<RwLock<Arc<StationCatalogue>>>,
+}
+
+impl SharedCatalogue {
+    pub fn new(catalogue: StationCatalogue) -> Self {
+        SharedCatalogue {
+            current: Arc::new(RwLock::new(Arc::new(catalogue))),
+        }
+    }
+
+    /// The current catalogue. It stays usable even if it's replaced while
+    /// it's held, so take it once per request.
+    pub fn get(&self) -> Arc<StationCatalogue> {
+        match self.current.read() {
+            Ok(current) => current.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn replace(&self, catalogue: StationCatalogue) {
+        let catalogue = Arc::new(catalogue);
+        match self.current.write() {
+            Ok(mut current) => *current = catalogue,
+            Err(poisoned) => *poisoned.into_inner() = catalogue,
+        }
+    }
+}
+
+/// What the data files looked like when last checked: the path, length
+/// and modification time of each of them, so that a file being replaced,
+/// renamed or rewritten counts as a change even if the newest time doesn't.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Fingerprint {
+    files: Vec<(PathBuf, u64, Option<SystemTime>)>,
+}
+
+impl Fingerprint {
+    pub fn of(dirs: &[PathBuf]) -> Self {
+        let mut files: Vec<_> = dirs
+            .iter()
+            .flat_map(|dir| fs::read_dir(dir).into_iter().flatten().flatten())
+            .map(|entry| {
+                let metadata = fs::metadata(entry.path()).ok();
+                (
+                    entry.path(),
+                    metadata.as_ref().map_or(0, |m| m.len()),
+                    metadata.and_then(|m| m.modified().ok()),
+                )
+            })
+            .collect();
+        files.sort();
+        Fingerprint { files }
+    }
+}
+
+/// Check `dirs` every `interval` and, when anything in them changes, build
+/// a new catalogue with `load` in the background and swap it in.
+///
+/// `load` returning `None` keeps the old catalogue, so a half-copied data
+/// refresh doesn't take the site down; it's tried again on the next change.
+pub fn watch<F>(
+    shared: SharedCatalogue,
+    dirs: Vec<PathBuf>,
+    interval: Duration,
+    load: F,
+) -> thread::JoinHandle<()>
+where
+    F: Fn() -> Option<StationCatalogue> + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut last = Fingerprint::of(&dirs);
+        loop {
+            thread::sleep(interval);
+            let now = Fingerprint::of(&dirs);
+            if now == last {
+                continue;
+            }
+            last = now;
+            info!("Data files changed, reloading the station catalogue");
+            match load() {
+                Some(catalogue) => {
+                    shared.replace(catalogue);
+                    info!("Reloaded the station catalogue");
+                }
+                None => warn!("Keeping the previous station catalogue"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::Coordinates;
+    use crate::stations::Station;
+
+    #[test]
+    fn it_should_swap_catalogues_without_disturbing_readers() {
+        let shared = SharedCatalogue::new(StationCatalogue::test());
+        let before = shared.get();
+
+        let replacement = StationCatalogue::new(
+            vec![Station {
+                name: "Point Atkinson".to_string(),
+                coordinates: Coordinates {
+                    lat: 49.3,
+                    lon: -123.2,
+                },
+                id: uuid::Uuid::new_v4(),
+                time_zone: None,
+            }],
+            vec![],
+        );
+        shared.clone().replace(replacement);
+
+        assert_eq!(
+            before.find_near(&Coordinates { lat: 0.0, lon: 0.0 }).name,
+            "Test Station"
+        );
+        let after = shared.get();
+        assert_eq!(
+            after.find_near(&Coordinates { lat: 0.0, lon: 0.0 }).name,
+            "Point Atkinson"
+        );
+    }
+
+    #[test]
+    fn it_should_notice_changed_files() {
+        let dir = std::env::temp_dir().join(format!("wtiirn-reload-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let dirs = vec![dir.clone()];
+
+        let empty = Fingerprint::of(&dirs);
+        fs::write(dir.join("stations.json"), "[]").unwrap();
+        let one_file = Fingerprint::of(&dirs);
+        // Rewritten straight away, so the modification time may not change.
+        fs::write(dir.join("stations.json"), "[ ]").unwrap();
+        let rewritten = Fingerprint::of(&dirs);
+        fs::rename(dir.join("stations.json"), dir.join("other.json")).unwrap();
+        let renamed = Fingerprint::of(&dirs);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_ne!(empty, one_file);
+        assert_eq!(one_file.files.len(), 1);
+        assert_ne!(one_file, rewritten);
+        assert_ne!(rewritten, renamed);
+        assert_eq!(Fingerprint::of(&dirs).files.len(), 0);
+    }
+}
@@ -0,0 +1,331 @@
+use http::{header, Method, Request, Response, StatusCode};
+use log::error;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+
+pub type HandlerResult = http::Result<Response<Vec<u8>>>;
+
+type Handler = Box<dyn Fn(&Request<Vec<u8>>, &Params) -> HandlerResult + Send + Sync>;
+type Before = Box<dyn Fn(&Request<Vec<u8>>) -> Option<HandlerResult> + Send + Sync>;
+type After = Box<dyn Fn(&Request<Vec<u8>>, &mut Response<Vec<u8>>) + Send + Sync>;
+
+/// The pattern of the route that answered a request, added to its
+/// response's extensions.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchedRoute(pub String);
+
+/// The parts of a request's path matched by a route's `:name` segments.
+#[derive(Debug, PartialEq, Default)]
+pub struct Params {
+    values: HashMap<String, String>,
+}
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+}
+
+struct Route {
+    method: Method,
+    pattern: String,
+    segments: Vec<Segment>,
+    handler: Handler,
+}
+
+impl Route {
+    fn matches(&self, path: &[&str]) -> Option<Params> {
+        if path.len() != self.segments.len() {
+            return None;
+        }
+        let mut params = Params::default();
+        for (segment, part) in self.segments.iter().zip(path) {
+            match segment {
+                Segment::Literal(literal) if literal == part => {}
+                Segment::Literal(_) => return None,
+                Segment::Param(name) => {
+                    params.values.insert(name.clone(), part.to_string());
+                }
+            }
+        }
+        Some(params)
+    }
+}
+
+/// Sends each request to the handler for its method and path.
+///
+/// Patterns are paths whose segments can be `:name` to match anything,
+/// e.g. `/station/:id/table`. `HEAD` requests are handled like `GET`
+/// without the body. A path that matches with the wrong method gets a
+/// 405, and one that doesn't match at all goes to the fallback.
+pub struct Router {
+    before: Vec<Before>,
+    after: Vec<After>,
+    routes: Vec<Route>,
+    fallback: Handler,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router {
+            before: vec![],
+            after: vec![],
+            routes: vec![],
+            fallback: Box::new(|_, _| status_response(StatusCode::NOT_FOUND)),
+        }
+    }
+
+    pub fn route<F>(mut self, method: Method, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&Request<Vec<u8>>, &Params) -> HandlerResult + Send + Sync + 'static,
+    {
+        let segments = split_path(pattern)
+            .into_iter()
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => Segment::Param(name.to_string()),
+                None => Segment::Literal(segment.to_string()),
+            })
+            .collect();
+        self.routes.push(Route {
+            method,
+            pattern: pattern.to_string(),
+            segments,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&Request<Vec<u8>>, &Params) -> HandlerResult + Send + Sync + 'static,
+    {
+        self.route(Method::GET, pattern, handler)
+    }
+
+    /// Handle requests that don't match any route.
+    pub fn fallback<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&Request<Vec<u8>>, &Params) -> HandlerResult + Send + Sync + 'static,
+    {
+        self.fallback = Box::new(handler);
+        self
+    }
+
+    /// Run `check` on every request before routing it. If it returns a
+    /// response, that's sent instead. Checks run in the order they're added.
+    pub fn before<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&Request<Vec<u8>>) -> Option<HandlerResult> + Send + Sync + 'static,
+    {
+        self.before.push(Box::new(check));
+        self
+    }
+
+    /// Run `finish` on every response, including ones from `before` checks
+    /// and errors, before it's sent.
+    pub fn after<F>(mut self, finish: F) -> Self
+    where
+        F: Fn(&Request<Vec<u8>>, &mut Response<Vec<u8>>) + Send + Sync + 'static,
+    {
+        self.after.push(Box::new(finish));
+        self
+    }
+
+    /// Produce the response to a request. Handlers that fail or panic
+    /// result in a 500.
+    pub fn handle(&self, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.dispatch(request)))
+            .unwrap_or_else(|_| {
+                error!(
+                    "Handler panicked for {} {}",
+                    request.method(),
+                    request.uri()
+                );
+                status_response(StatusCode::INTERNAL_SERVER_ERROR)
+            });
+        let mut response = result.unwrap_or_else(|e| {
+            error!(
+                "Could not build the response to {} {}: {}",
+                request.method(),
+                request.uri(),
+                e
+            );
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(vec![])
+                .expect("an empty response is valid")
+        });
+        for finish in &self.after {
+            finish(request, &mut response);
+        }
+        if request.method() == Method::HEAD {
+            response.body_mut().clear();
+        }
+        response
+    }
+
+    fn dispatch(&self, request: &Request<Vec<u8>>) -> HandlerResult {
+        for check in &self.before {
+            if let Some(response) = check(request) {
+                return response;
+            }
+        }
+
+        let method = match request.method() {
+            &Method::HEAD => &Method::GET,
+            method => method,
+        };
+        let path = split_path(request.uri().path());
+        let mut allowed = vec![];
+        for route in &self.routes {
+            if let Some(params) = route.matches(&path) {
+                if route.method == *method {
+                    let mut response = (route.handler)(request, &params)?;
+                    response
+                        .extensions_mut()
+                        .insert(MatchedRoute(route.pattern.clone()));
+                    return Ok(response);
+                }
+                allowed.push(route.method.as_str());
+            }
+        }
+        if allowed.is_empty() {
+            return (self.fallback)(request, &Params::default());
+        }
+        Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, allowed.join(", "))
+            .body(vec![])
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.trim_start_matches('/').split('/').collect()
+}
+
+/// An empty response with the given status.
+pub fn status_response(status: StatusCode) -> HandlerResult {
+    Response::builder().status(status).body(vec![])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get(uri: &str) -> Request<Vec<u8>> {
+        Request::builder().uri(uri).body(vec![]).unwrap()
+    }
+
+    fn body(text: String) -> HandlerResult {
+        Response::builder().body(text.into_bytes())
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/", |_, _| body("home".to_string()))
+            .get("/station/:id", |_, params| {
+                body(format!("station {}", params.get("id").unwrap()))
+            })
+            .get("/station/:id/table", |_, params| {
+                body(format!("table {}", params.get("id").unwrap()))
+            })
+            .route(Method::POST, "/station/:id", |_, _| {
+                status_response(StatusCode::NO_CONTENT)
+            })
+            .get("/broken", |_, _| panic!("oops"))
+    }
+
+    #[test]
+    fn it_should_route_by_path_and_capture_params() {
+        let router = router();
+
+        let response = router.handle(&get("/"));
+        assert_eq!(response.body(), b"home");
+        let response = router.handle(&get("/station/abc?days=3"));
+        assert_eq!(response.body(), b"station abc");
+        let response = router.handle(&get("/station/abc/table"));
+        assert_eq!(response.body(), b"table abc");
+        assert_eq!(
+            response.extensions().get::<MatchedRoute>(),
+            Some(&MatchedRoute("/station/:id/table".to_string()))
+        );
+
+        for uri in &["/station", "/station/abc/table/x", "/station/abc/", "/nope"] {
+            assert_eq!(router.handle(&get(uri)).status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn it_should_route_by_method() {
+        let router = router();
+        let post = Request::builder()
+            .method(Method::POST)
+            .uri("/station/abc")
+            .body(vec![])
+            .unwrap();
+        let delete = Request::builder()
+            .method(Method::DELETE)
+            .uri("/station/abc")
+            .body(vec![])
+            .unwrap();
+        let head = Request::builder()
+            .method(Method::HEAD)
+            .uri("/station/abc")
+            .body(vec![])
+            .unwrap();
+
+        assert_eq!(router.handle(&post).status(), StatusCode::NO_CONTENT);
+        let response = router.handle(&delete);
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[header::ALLOW], "GET, POST");
+        let response = router.handle(&head);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.body().is_empty());
+    }
+
+    #[test]
+    fn it_should_let_checks_answer_first() {
+        let router = router().before(|request| {
+            if request.uri().path() == "/" {
+                Some(status_response(StatusCode::FORBIDDEN))
+            } else {
+                None
+            }
+        });
+
+        assert_eq!(router.handle(&get("/")).status(), StatusCode::FORBIDDEN);
+        assert_eq!(router.handle(&get("/station/a")).status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn it_should_let_every_response_be_finished() {
+        let router = router().after(|request, response| {
+            let path = request.uri().path().to_string();
+            response
+                .headers_mut()
+                .insert("x-path", path.parse().unwrap());
+        });
+
+        for uri in &["/", "/nope", "/broken"] {
+            assert_eq!(router.handle(&get(uri)).headers()["x-path"], *uri);
+        }
+    }
+
+    #[test]
+    fn it_should_turn_panics_into_errors() {
+        let response = router().handle(&get("/broken"));
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
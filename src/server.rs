@@ -0,0 +1,427 @@
+//! A small HTTP/1.1 server: one request per connection, handled by a fixed
+//! pool of worker threads.
+
+use http::header::{self, HeaderValue};
+use http::{Request, Response, StatusCode, Version};
+use log::{debug, info, warn};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the accept loop sleeps when there's nobody waiting, which is
+/// also how long shutting down can take to notice.
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+const MAX_HEADERS: usize = 64;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Options {
+    /// How many requests are handled at once.
+    pub workers: usize,
+    /// How many connections can wait for a worker before new ones are
+    /// turned away with a 503.
+    pub queue: usize,
+    /// How long a client has to send its whole request, counted from when
+    /// it connected, and how long each write of the response may take.
+    pub timeout: Duration,
+    /// The largest request, headers and body together, that's accepted.
+    pub max_request_bytes: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            workers: 8,
+            queue: 64,
+            timeout: Duration::from_secs(30),
+            max_request_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Answer requests on `listener` with `handler` until `shutdown` is set,
+/// then finish the requests already accepted and return.
+///
+/// Connections turned away without reaching `handler`, because the queue is
+/// full or the request couldn't be read, are passed to `rejected` with the
+/// response they were sent and how long they took since being accepted.
+pub fn serve<H, R>(
+    listener: TcpListener,
+    options: &Options,
+    handler: H,
+    rejected: R,
+    shutdown: &AtomicBool,
+) -> io::Result<()>
+where
+    H: Fn(&Request<Vec<u8>>) -> Response<Vec<u8>> + Send + Sync + 'static,
+    R: Fn(&Response<Vec<u8>>, Duration) + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let rejected = Arc::new(rejected);
+    let (sender, receiver) = mpsc::sync_channel::<(TcpStream, Instant)>(options.queue);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let workers: Vec<_> = (0..options.workers.max(1))
+        .map(|_| {
+            let receiver = receiver.clone();
+            let handler = handler.clone();
+            let rejected = rejected.clone();
+            let options = options.clone();
+            thread::spawn(move || work(&receiver, &options, &*handler, &*rejected))
+        })
+        .collect();
+
+    listener.set_nonblocking(true)?;
+    while !shutdown.load(Ordering::SeqCst) {
+        let (stream, accepted) = match listener.accept() {
+            Ok((stream, _)) => (stream, Instant::now()),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL);
+                continue;
+            }
+            Err(e) => {
+                warn!("Could not accept a connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = prepare(&stream, options) {
+            warn!("Could not set up a connection: {}", e);
+            continue;
+        }
+        match sender.try_send((stream, accepted)) {
+            Ok(()) => {}
+            Err(TrySendError::Full((mut stream, accepted))) => {
+                let response = status(StatusCode::SERVICE_UNAVAILABLE);
+                let _ = write_response(&mut stream, &response);
+                rejected(&response, accepted.elapsed());
+            }
+            Err(TrySendError::Disconnected(_)) => break,
+        }
+    }
+
+    info!("Shutting down, finishing requests in progress");
+    drop(sender);
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+fn prepare(stream: &TcpStream, options: &Options) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(options.timeout))
+}
+
+/// Handle connections from the queue until it's closed and empty.
+fn work<H, R>(
+    receiver: &Mutex<Receiver<(TcpStream, Instant)>>,
+    options: &Options,
+    handler: &H,
+    rejected: &R,
+) where
+    H: Fn(&Request<Vec<u8>>) -> Response<Vec<u8>>,
+    R: Fn(&Response<Vec<u8>>, Duration),
+{
+    loop {
+        let next = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(poisoned) => poisoned.into_inner().recv(),
+        };
+        let (mut stream, accepted) = match next {
+            Ok(next) => next,
+            Err(_) => return,
+        };
+        let mut reader = Deadline {
+            stream: &stream,
+            deadline: accepted + options.timeout,
+        };
+        match read_request(&mut reader, options.max_request_bytes) {
+            Ok(request) => {
+                let response = panic::catch_unwind(AssertUnwindSafe(|| handler(&request)))
+                    .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR));
+                send(&mut stream, &response);
+            }
+            Err(code) => {
+                let response = status(code);
+                send(&mut stream, &response);
+                rejected(&response, accepted.elapsed());
+            }
+        }
+    }
+}
+
+fn send(stream: &mut TcpStream, response: &Response<Vec<u8>>) {
+    if let Err(e) = write_response(stream, response) {
+        debug!("Could not send a response: {}", e);
+    }
+}
+
+/// Reads from a connection until its deadline, however the time is spent,
+/// so a client sending its request a byte at a time can't hold a worker
+/// for longer than a client that sends nothing.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self
+            .deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| *remaining > Duration::from_millis(0))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "request deadline passed"))?;
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Read and parse one request, or say which error status to answer with.
+fn read_request(stream: &mut impl Read, max_bytes: usize) -> Result<Request<Vec<u8>>, StatusCode> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let read = stream.read(&mut chunk).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => StatusCode::REQUEST_TIMEOUT,
+            _ => StatusCode::BAD_REQUEST,
+        })?;
+        if read == 0 {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        let header_length = match parsed.parse(&buffer) {
+            Ok(httparse::Status::Complete(length)) => length,
+            Ok(httparse::Status::Partial) if buffer.len() < max_bytes => continue,
+            Ok(httparse::Status::Partial) => return Err(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(_) => return Err(StatusCode::BAD_REQUEST),
+        };
+
+        let mut request = Request::builder();
+        request
+            .method(parsed.method.unwrap_or("GET"))
+            .uri(parsed.path.unwrap_or("/"))
+            .version(match parsed.version {
+                Some(0) => Version::HTTP_10,
+                _ => Version::HTTP_11,
+            });
+        let mut content_length = 0;
+        for h in parsed.headers.iter() {
+            if h.name.eq_ignore_ascii_case("content-length") {
+                content_length = std::str::from_utf8(h.value)
+                    .ok()
+                    .and_then(|v| v.trim().parse().ok())
+                    .ok_or(StatusCode::BAD_REQUEST)?;
+            }
+            request.header(h.name, h.value);
+        }
+        if header_length
+            .checked_add(content_length)
+            .map_or(true, |length| length > max_bytes)
+        {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let mut body = buffer.split_off(header_length);
+        if body.len() < content_length {
+            let mut rest = vec![0; content_length - body.len()];
+            stream.read_exact(&mut rest).map_err(|e| match e.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => StatusCode::REQUEST_TIMEOUT,
+                _ => StatusCode::BAD_REQUEST,
+            })?;
+            body.extend(rest);
+        }
+        body.truncate(content_length);
+        return request.body(body).map_err(|_| StatusCode::BAD_REQUEST);
+    }
+}
+
+/// Write a response, closing the connection afterwards.
+fn write_response(stream: &mut impl Write, response: &Response<Vec<u8>>) -> io::Result<()> {
+    let status = response.status();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_str(),
+        status.canonical_reason().unwrap_or("")
+    );
+    for (name, value) in response.headers() {
+        if name == header::CONTENT_LENGTH || name == header::CONNECTION {
+            continue;
+        }
+        head.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            value.to_str().unwrap_or_default()
+        ));
+    }
+    let length = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .filter(|_| response.body().is_empty())
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from(response.body().len()));
+    head.push_str(&format!(
+        "content-length: {}\r\nconnection: close\r\n\r\n",
+        length.to_str().unwrap_or_default()
+    ));
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body())?;
+    stream.flush()
+}
+
+fn status(code: StatusCode) -> Response<Vec<u8>> {
+    let mut response = Response::new(vec![]);
+    *response.status_mut() = code;
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::Shutdown;
+    use std::sync::mpsc::channel;
+
+    fn request(raw: &str) -> Result<Request<Vec<u8>>, StatusCode> {
+        read_request(&mut raw.as_bytes(), 1024)
+    }
+
+    #[test]
+    fn it_should_parse_requests() {
+        let request = request(
+            "POST /station/abc?days=3 HTTP/1.1\r\n\
+             Host: example.com\r\n\
+             Content-Length: 5\r\n\
+             \r\n\
+             hello",
+        )
+        .unwrap();
+
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.uri().path(), "/station/abc");
+        assert_eq!(request.uri().query(), Some("days=3"));
+        assert_eq!(request.headers()[header::HOST], "example.com");
+        assert_eq!(request.body(), b"hello");
+    }
+
+    #[test]
+    fn it_should_reject_bad_requests() {
+        assert_eq!(
+            request("NOT HTTP\r\n\r\n").unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            request("GET / HTTP/1.1\r\nHost:").unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            request("POST / HTTP/1.1\r\nContent-Length: 2000\r\n\r\n").unwrap_err(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            request("POST / HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n").unwrap_err(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[test]
+    fn it_should_write_responses() {
+        let response = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(b"nope".to_vec())
+            .unwrap();
+        let mut written = vec![];
+
+        write_response(&mut written, &response).unwrap();
+
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "HTTP/1.1 404 Not Found\r\n\
+             content-type: text/plain\r\n\
+             content-length: 4\r\n\
+             connection: close\r\n\
+             \r\n\
+             nope"
+        );
+    }
+
+    #[test]
+    fn it_should_serve_until_shut_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (done, finished) = channel();
+        let flag = shutdown.clone();
+        thread::spawn(move || {
+            let handler = |request: &Request<Vec<u8>>| {
+                Response::new(request.uri().path().as_bytes().to_vec())
+            };
+            let rejected = |_: &Response<Vec<u8>>, _| {};
+            serve(listener, &Options::default(), handler, rejected, &flag).unwrap();
+            done.send(()).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET /hello HTTP/1.1\r\n\r\n").unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n/hello"));
+
+        shutdown.store(true, Ordering::SeqCst);
+        finished.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn it_should_time_out_requests_sent_too_slowly() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (rejections, rejected) = channel();
+        let flag = shutdown.clone();
+        let server = thread::spawn(move || {
+            let options = Options {
+                timeout: Duration::from_millis(300),
+                ..Options::default()
+            };
+            let handler = |_: &Request<Vec<u8>>| Response::new(vec![]);
+            let rejections = Mutex::new(rejections);
+            let rejected = move |response: &Response<Vec<u8>>, _| {
+                let _ = rejections.lock().unwrap().send(response.status());
+            };
+            serve(listener, &options, handler, rejected, &flag).unwrap();
+        });
+
+        // Each byte arrives well within the timeout, but the request as a
+        // whole doesn't.
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        thread::spawn(move || {
+            for byte in b"GET /hello HTTP/1.1\r\n\r\n".iter() {
+                if writer.write_all(&[*byte]).is_err() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        let started = Instant::now();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(
+            rejected.recv_timeout(Duration::from_secs(5)),
+            Ok(StatusCode::REQUEST_TIMEOUT)
+        );
+
+        shutdown.store(true, Ordering::SeqCst);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+use chrono::prelude::*;
+use http::header::{self, HeaderMap, HeaderValue};
+use http::{Method, Request, Response, StatusCode};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Serves the files underneath a single directory, refusing anything that
+/// would resolve outside of it.
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        StaticFiles { root: root.into() }
+    }
+
+    /// Map a request path onto a file inside the root directory.
+    ///
+    /// Returns `None` if the path doesn't name a regular file, tries to
+    /// escape the root (`..`, absolute paths, symlinks pointing elsewhere)
+    /// or names a hidden file.
+    pub fn resolve(&self, uri_path: &str) -> Option<PathBuf> {
+        let relative = Path::new(uri_path.trim_start_matches('/'));
+        let safe = relative.components().all(|c| match c {
+            Component::Normal(part) => part.to_str().map_or(false, |p| !p.starts_with('.')),
+            _ => false,
+        });
+        if !safe || uri_path.contains('\\') || relative.as_os_str().is_empty() {
+            return None;
+        }
+
+        let root = self.root.canonicalize().ok()?;
+        let path = root.join(relative).canonicalize().ok()?;
+        if path.starts_with(&root) && path.is_file() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Build the response for a file returned by `resolve`, answering
+    /// conditional requests with `304 Not Modified` where possible.
+    pub fn serve<T>(
+        &self,
+        path: &Path,
+        request: &Request<T>,
+        response: &mut http::response::Builder,
+    ) -> http::Result<Response<Vec<u8>>> {
+        let metadata = match fs::metadata(path) {
+            Ok(m) => m,
+            Err(_) => return response.status(StatusCode::NOT_FOUND).body(vec![]),
+        };
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let modified_secs = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let etag = format!("\"{:x}-{:x}\"", metadata.len(), modified_secs);
+        let last_modified = http_date(modified);
+
+        response
+            .header(header::ETAG, etag.as_str())
+            .header(header::LAST_MODIFIED, last_modified.as_str())
+            .header(header::CACHE_CONTROL, "public, max-age=3600");
+
+        if is_not_modified(request.headers(), &etag, modified_secs) {
+            return response.status(StatusCode::NOT_MODIFIED).body(vec![]);
+        }
+
+        let body = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(_) => return response.status(StatusCode::NOT_FOUND).body(vec![]),
+        };
+        response
+            .header(header::CONTENT_TYPE, content_type(path))
+            .header(header::CONTENT_LENGTH, body.len());
+        if request.method() == Method::HEAD {
+            response.body(vec![])
+        } else {
+            response.body(body)
+        }
+    }
+}
+
+fn is_not_modified(headers: &HeaderMap<HeaderValue>, etag: &str, modified_secs: u64) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since (RFC 7232 section 6).
+    if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+        return value.to_str().map_or(false, |v| {
+            v.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag)
+        });
+    }
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+        .map_or(false, |since| since.timestamp() >= modified_secs as i64)
+}
+
+fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format(HTTP_DATE_FORMAT)
+        .to_string()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "application/javascript; charset=utf-8",
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn public() -> StaticFiles {
+        StaticFiles::new("public")
+    }
+
+    fn get(uri: &str) -> http::request::Builder {
+        let mut builder = Request::builder();
+        builder.method(Method::GET).uri(uri);
+        builder
+    }
+
+    #[test]
+    fn it_should_resolve_files_in_the_root() {
+        assert!(public().resolve("/style.css").is_some());
+        assert!(public().resolve("/getlocation.js").is_some());
+        assert_eq!(public().resolve("/nope.css"), None);
+        assert_eq!(public().resolve("/"), None);
+    }
+
+    #[test]
+    fn it_should_refuse_path_traversal() {
+        assert_eq!(public().resolve("/../Cargo.toml"), None);
+        assert_eq!(public().resolve("/./style.css"), None);
+        assert_eq!(public().resolve("//etc/passwd"), None);
+        assert_eq!(public().resolve("/..\\Cargo.toml"), None);
+        assert_eq!(public().resolve("/.hidden"), None);
+    }
+
+    #[test]
+    fn it_should_serve_with_content_type_and_validators() {
+        let statics = public();
+        let path = statics.resolve("/style.css").unwrap();
+        let request = get("/style.css").body(()).unwrap();
+
+        let response = statics
+            .serve(&path, &request, &mut Response::builder())
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/css; charset=utf-8"
+        );
+        assert!(response.headers().contains_key(header::ETAG));
+        assert!(response.headers().contains_key(header::LAST_MODIFIED));
+        assert!(!response.body().is_empty());
+    }
+
+    #[test]
+    fn it_should_answer_conditional_requests_with_not_modified() {
+        let statics = public();
+        let path = statics.resolve("/getlocation.js").unwrap();
+        let first = statics
+            .serve(
+                &path,
+                &get("/getlocation.js").body(()).unwrap(),
+                &mut Response::builder(),
+            )
+            .unwrap();
+        let etag = first.headers()[header::ETAG].clone();
+        let last_modified = first.headers()[header::LAST_MODIFIED].clone();
+
+        let by_etag = get("/getlocation.js")
+            .header(header::IF_NONE_MATCH, etag)
+            .body(())
+            .unwrap();
+        let response = statics
+            .serve(&path, &by_etag, &mut Response::builder())
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.body().is_empty());
+
+        let by_date = get("/getlocation.js")
+            .header(header::IF_MODIFIED_SINCE, last_modified)
+            .body(())
+            .unwrap();
+        let response = statics
+            .serve(&path, &by_date, &mut Response::builder())
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let stale = get("/getlocation.js")
+            .header(header::IF_NONE_MATCH, "\"something-else\"")
+            .body(())
+            .unwrap();
+        let response = statics
+            .serve(&path, &stale, &mut Response::builder())
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
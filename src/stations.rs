@@ -1,89 +1,355 @@
-use crate::model::{Coordinates, TidePrediction};
-use serde::Deserialize;
+use crate::compute::find::bracketing_pair;
+use crate::compute::gcd::{earth_radius, great_circle_distance};
+use crate::compute::spatial::SpatialIndex;
+use crate::model::{Coordinates, TidePrediction, TidePredictionPair};
+use crate::zone::Zone;
+use chrono::prelude::*;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use uom::si::f64::*;
+use uom::si::length::kilometer;
 use uuid::Uuid;
 
+pub mod report;
+pub mod validate;
+
+use report::{FileReport, LoadReport, Position};
+
 /// The generic information about a tide station, divorced
 /// from meta-data like "how are the tides predicted" and
 /// "who's responsible for this station".
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct Station {
     pub name: String,
     pub coordinates: Coordinates,
     pub id: Uuid,
+    /// IANA name of the station's local time zone, e.g. `America/Vancouver`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
 }
 
 impl Station {
+    /// The station's local time zone, approximated from its longitude
+    /// when the data doesn't say.
+    pub fn zone(&self) -> Zone {
+        self.named_zone()
+            .unwrap_or_else(|| Zone::nautical(&self.coordinates))
+    }
+
+    /// The station's local time zone, only if the data names one.
+    pub fn named_zone(&self) -> Option<Zone> {
+        self.time_zone
+            .as_ref()
+            .and_then(|name| Zone::from_name(name))
+    }
+
     pub fn generate_id(name: &str, source_id: u32) -> Uuid {
         let name_bytes = format!("{}{}", name, source_id).into_bytes();
         Uuid::new_v5(&Uuid::NAMESPACE_OID, &name_bytes)
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct PredictionsWithId {
     pub station_id: Uuid,
     pub predictions: Vec<TidePrediction>,
 }
 
+/// How many of the stations closest to a location are considered when the
+/// nearest one can't be used. Past this the substitute would be too far away
+/// to say much about the tide where the user is.
+const SUBSTITUTE_CANDIDATES: usize = 10;
+
+/// How far from a location a substitute station may be, in kilometres.
+/// Tides farther away than this are no guide to the tide where the user is,
+/// so showing no tide information is more honest.
+const MAX_SUBSTITUTE_DISTANCE_KM: f64 = 100.0;
+
+/// Why the nearest station was passed over for one farther away.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SubstitutionReason {
+    NoPredictions,
+    PredictionsDontCoverTime,
+}
+
+impl fmt::Display for SubstitutionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubstitutionReason::NoPredictions => write!(f, "has no tide predictions loaded"),
+            SubstitutionReason::PredictionsDontCoverTime => {
+                write!(f, "has no tide predictions for this time")
+            }
+        }
+    }
+}
+
+/// The nearest station, when it was skipped in favour of another.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Substitution {
+    pub nearest: Station,
+    pub reason: SubstitutionReason,
+}
+
+/// Where the catalogue's data files are read from.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataPaths {
+    /// JSON files of stations.
+    pub stations: PathBuf,
+    /// JSON files of predictions.
+    pub predictions: PathBuf,
+    /// NOAA high/low XML files, which describe their own stations.
+    pub noaa: PathBuf,
+}
+
+impl DataPaths {
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        vec![
+            self.stations.clone(),
+            self.predictions.clone(),
+            self.noaa.clone(),
+        ]
+    }
+}
+
+impl Default for DataPaths {
+    fn default() -> Self {
+        DataPaths {
+            stations: PathBuf::from("data/stations"),
+            predictions: PathBuf::from("data/predictions"),
+            noaa: PathBuf::from("data/noaa"),
+        }
+    }
+}
+
 /// Queryable repository of stations.
 pub struct StationCatalogue {
     stations: Vec<Station>,
-    predictions: Vec<PredictionsWithId>,
+    /// Each station's predictions, sorted by time without duplicates.
+    predictions: HashMap<Uuid, Vec<TidePrediction>>,
+    /// Spatial index over `stations`, which must be rebuilt whenever they change.
+    index: SpatialIndex,
+}
+
+/// See `StationCatalogue::summary`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CatalogueSummary {
+    pub stations: usize,
+    /// How many stations have predictions.
+    pub prediction_series: usize,
+    pub earliest: Option<DateTime<FixedOffset>>,
+    pub latest: Option<DateTime<FixedOffset>>,
+    /// The end of the shortest series: when the first station runs out of
+    /// predictions.
+    pub first_to_run_out: Option<DateTime<FixedOffset>>,
 }
 
 impl StationCatalogue {
-    pub fn empty() -> Self {
+    pub fn new(stations: Vec<Station>, predictions: Vec<PredictionsWithId>) -> Self {
+        let index = build_index(&stations);
         StationCatalogue {
-            stations: vec![],
-            predictions: vec![],
+            stations,
+            predictions: merge_predictions(predictions),
+            index,
         }
     }
 
+    pub fn empty() -> Self {
+        StationCatalogue::new(vec![], vec![])
+    }
+
     pub fn test() -> Self {
-        StationCatalogue {
-            stations: vec![Station {
+        StationCatalogue::new(
+            vec![Station {
                 name: "Test Station".into(),
                 coordinates: Coordinates { lat: 0.0, lon: 0.0 },
                 id: Uuid::new_v4(),
+                time_zone: None,
             }],
-            predictions: vec![],
-        }
+            vec![],
+        )
     }
 
-    /// Initialize a catalogue from a suitable data source.
-    /// Panics if there isn't at least one tide station in
-    /// the initialized catalogue.
-    pub fn load() -> Self {
-        println!("Initializing Station Catalogue");
-        let stations =
-            load_stations_from_dir(Path::new("data/stations")).expect("failed to load stations");
-        println!("Loaded {} total stations", stations.len());
-        let predictions = load_predictions_from_dir(Path::new("data/predictions"))
-            .expect("failed to load predcitions");
-        println!("Loaded {} prediction collections", predictions.len());
+    /// Initialize a catalogue from the files in `paths`. The catalogue
+    /// may be empty; check `is_empty` before querying it.
+    pub fn load(paths: &DataPaths) -> Self {
+        let (catalogue, report) = StationCatalogue::load_with_report(paths);
+        report.log();
+        catalogue
+    }
 
-        StationCatalogue {
-            stations,
-            predictions,
+    /// Like `load`, but also reports on every data file that was read. A
+    /// data directory that can't be read is reported as a skipped file.
+    pub fn load_with_report(paths: &DataPaths) -> (Self, LoadReport) {
+        let mut report = LoadReport::default();
+        let catalogue = StationCatalogue::read(paths, &mut report, true)
+            .expect("unreadable directories are reported rather than returned");
+        (catalogue, report)
+    }
+
+    /// Like `load_with_report`, but returns an error when the data
+    /// directories can't be read.
+    pub fn try_load(paths: &DataPaths) -> Result<(Self, LoadReport), Box<dyn Error>> {
+        let mut report = LoadReport::default();
+        let catalogue = StationCatalogue::read(paths, &mut report, false)?;
+        Ok((catalogue, report))
+    }
+
+    fn read(
+        paths: &DataPaths,
+        report: &mut LoadReport,
+        skip_unreadable_dirs: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        info!("Initializing Station Catalogue");
+        let stations = match load_stations_from_dir(&paths.stations, report) {
+            Ok(stations) => stations,
+            Err(e) if skip_unreadable_dirs => {
+                report.file(&paths.stations).skip(None, e);
+                vec![]
+            }
+            Err(e) => return Err(e),
+        };
+        info!("Loaded {} total stations", stations.len());
+        let predictions = match load_predictions_from_dir(&paths.predictions, report) {
+            Ok(predictions) => predictions,
+            Err(e) if skip_unreadable_dirs => {
+                report.file(&paths.predictions).skip(None, e);
+                vec![]
+            }
+            Err(e) => return Err(e),
+        };
+        info!("Loaded {} prediction collections", predictions.len());
+
+        let (stations, predictions) = add_noaa_files(stations, predictions, &paths.noaa, report);
+        Ok(StationCatalogue::new(stations, predictions))
+    }
+
+    /// True if there are no stations. Most queries need at least one.
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+
+    /// How many stations have predictions until at least `time`.
+    pub fn stations_covered_until(&self, time: DateTime<FixedOffset>) -> usize {
+        self.stations
+            .iter()
+            .filter_map(|station| self.predictions_for_station(station))
+            .filter(|predictions| predictions.last().map_or(false, |p| p.time >= time))
+            .count()
+    }
+
+    /// How much data the catalogue holds and the time it covers.
+    /// Predictions for stations that aren't in the catalogue are left out.
+    pub fn summary(&self) -> CatalogueSummary {
+        let series = self
+            .stations
+            .iter()
+            .filter_map(|station| self.predictions_for_station(station))
+            .filter(|p| !p.is_empty());
+        let ranges: Vec<_> = series.map(|p| (p[0].time, p[p.len() - 1].time)).collect();
+        CatalogueSummary {
+            stations: self.stations.len(),
+            prediction_series: ranges.len(),
+            earliest: ranges.iter().map(|(first, _)| *first).min(),
+            latest: ranges.iter().map(|(_, last)| *last).max(),
+            first_to_run_out: ranges.iter().map(|(_, last)| *last).min(),
         }
     }
 
     /// Find the station nearest to the given coordinates.
     pub fn find_near(&self, coordinates: &Coordinates) -> &Station {
-        use crate::compute::gcd::great_circle_distance;
-        let cmp_distance = |s1: &&Station, s2: &&Station| {
-            let d1 = great_circle_distance(&s1.coordinates, coordinates);
-            let d2 = great_circle_distance(&s2.coordinates, coordinates);
-            d1.partial_cmp(&d2).expect("Distances shouldn't be NaN")
+        let nearest = self
+            .index
+            .nearest(coordinates)
+            .expect("StationCatalogue has at least one station, so there must be a minimum");
+        &self.stations[nearest]
+    }
+
+    /// Find the station nearest to the given coordinates whose predictions
+    /// cover `time`, falling back to the nearest station if none of the
+    /// closest few within `MAX_SUBSTITUTE_DISTANCE_KM` do. When a farther
+    /// station is chosen, the nearest one and the reason it was skipped are
+    /// returned along with it.
+    pub fn find_near_with_predictions(
+        &self,
+        coordinates: &Coordinates,
+        time: DateTime<FixedOffset>,
+    ) -> (&Station, Option<Substitution>) {
+        let nearest = self.find_near(coordinates);
+        let reason = match self.predictions_for_station(nearest) {
+            None => SubstitutionReason::NoPredictions,
+            Some(preds) if !covers(preds, time) => SubstitutionReason::PredictionsDontCoverTime,
+            Some(_) => return (nearest, None),
         };
-        self.stations
-            .iter()
-            .min_by(cmp_distance)
-            .expect("StationCatalogue has at least one station, so there must be a minimum")
+        let substitute = self
+            .find_k_nearest(coordinates, SUBSTITUTE_CANDIDATES)
+            .into_iter()
+            .take_while(|(_, distance)| distance.get::<kilometer>() <= MAX_SUBSTITUTE_DISTANCE_KM)
+            .map(|(station, _)| station)
+            .find(|station| {
+                self.predictions_for_station(station)
+                    .map_or(false, |preds| covers(preds, time))
+            });
+        match substitute {
+            Some(station) => (
+                station,
+                Some(Substitution {
+                    nearest: nearest.clone(),
+                    reason,
+                }),
+            ),
+            None => (nearest, None),
+        }
+    }
+
+    /// The `k` stations closest to the given coordinates, nearest first,
+    /// along with their distance from them.
+    pub fn find_k_nearest(&self, coordinates: &Coordinates, k: usize) -> Vec<(&Station, Length)> {
+        self.with_distances(coordinates, self.index.k_nearest(coordinates, k))
+    }
+
+    /// Every station within `radius` of the given coordinates, nearest first,
+    /// along with their distance from them.
+    pub fn find_within(
+        &self,
+        coordinates: &Coordinates,
+        radius: Length,
+    ) -> Vec<(&Station, Length)> {
+        let angle = (radius / earth_radius()).value;
+        let mut found = self.with_distances(coordinates, self.index.within(coordinates, angle));
+        found.retain(|(_, distance)| *distance <= radius);
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).expect("Distances shouldn't be NaN"));
+        found
+    }
+
+    fn with_distances(
+        &self,
+        coordinates: &Coordinates,
+        indices: Vec<usize>,
+    ) -> Vec<(&Station, Length)> {
+        indices
+            .into_iter()
+            .map(|i| {
+                let station = &self.stations[i];
+                (
+                    station,
+                    great_circle_distance(&station.coordinates, coordinates),
+                )
+            })
+            .collect()
+    }
+
+    /// Look up a station by its id.
+    pub fn find_by_id(&self, id: &Uuid) -> Option<&Station> {
+        self.stations.iter().find(|s| &s.id == id)
     }
 
     /// Add a station's data to this catalogue, assigning it an appropriate unique id.
@@ -94,102 +360,274 @@ impl StationCatalogue {
             name: name.to_owned(),
             coordinates: *coordinates,
             id,
+            time_zone: None,
         };
         self.stations.push(station);
-        self.predictions.push(PredictionsWithId {
-            station_id: id,
-            predictions: predictions.to_vec(),
-        });
+        self.index = build_index(&self.stations);
+        if !predictions.is_empty() {
+            let mut predictions = predictions.to_vec();
+            sort_and_dedup(&mut predictions);
+            self.predictions.insert(id, predictions);
+        }
     }
 
-    pub fn predictions_for_station(&self, station: &Station) -> Option<Vec<TidePrediction>> {
-        Some(
-            self.predictions
-                .iter()
-                .filter(|x| x.station_id == station.id)
-                .flat_map(|x| x.predictions.clone())
-                .collect(),
-        )
+    /// All of the predictions for a station sorted by time, or `None` if
+    /// there aren't any.
+    pub fn predictions_for_station(&self, station: &Station) -> Option<&[TidePrediction]> {
+        self.predictions.get(&station.id).map(|p| p.as_slice())
+    }
+
+    /// The predictions for a station needed to describe the tide from
+    /// `start` to `end`: those in between plus the ones either side.
+    pub fn predictions_covering(
+        &self,
+        station: &Station,
+        start: DateTime<FixedOffset>,
+        end: DateTime<FixedOffset>,
+    ) -> &[TidePrediction] {
+        let predictions = self.predictions_for_station(station).unwrap_or(&[]);
+        let first = predictions
+            .partition_point(|p| p.is_before(start))
+            .saturating_sub(1);
+        let last = predictions.partition_point(|p| p.time <= end);
+        let last = (last + 1).min(predictions.len());
+        &predictions[first..last.max(first)]
+    }
+
+    /// The predictions immediately before and after `time` for a station.
+    pub fn pair_for_station(
+        &self,
+        station: &Station,
+        time: DateTime<FixedOffset>,
+    ) -> Option<TidePredictionPair> {
+        self.predictions_for_station(station)
+            .and_then(|preds| bracketing_pair(preds, time))
+    }
+}
+
+/// Whether there are predictions on both sides of `time`. `predictions`
+/// must be sorted by time.
+fn covers(predictions: &[TidePrediction], time: DateTime<FixedOffset>) -> bool {
+    match (predictions.first(), predictions.last()) {
+        (Some(first), Some(last)) => first.is_before(time) && !last.is_before(time),
+        _ => false,
     }
 }
 
-fn load_stations_from_dir(path: &Path) -> Result<Vec<Station>, Box<dyn Error>> {
-    Ok(fs::read_dir(path)?
-        .flat_map(|file| load_stations_from_json(&file?.path()))
-        .flat_map(|item| item)
-        .collect())
+/// Combine prediction collections that belong to the same station, e.g. from
+/// overlapping data files, into one series per station.
+fn merge_predictions(predictions: Vec<PredictionsWithId>) -> HashMap<Uuid, Vec<TidePrediction>> {
+    let mut merged: HashMap<Uuid, Vec<TidePrediction>> = HashMap::new();
+    for collection in predictions {
+        merged
+            .entry(collection.station_id)
+            .or_default()
+            .extend(collection.predictions);
+    }
+    merged.retain(|_, series| !series.is_empty());
+    for series in merged.values_mut() {
+        sort_and_dedup(series);
+    }
+    merged
 }
 
-fn load_stations_from_json(path: &Path) -> Result<Vec<Station>, Box<dyn Error>> {
-    let string = read_file(path)?;
-    Ok(parse_stations(&string))
+/// Sort by time, keeping only the first of any predictions for the same instant.
+fn sort_and_dedup(predictions: &mut Vec<TidePrediction>) {
+    predictions.sort_by_key(|p| p.time);
+    predictions.dedup_by_key(|p| p.time);
 }
 
-fn load_predictions_from_dir(path: &Path) -> Result<Vec<PredictionsWithId>, Box<dyn Error>> {
-    Ok(fs::read_dir(path)?
-        .flat_map(|file| load_predictions_from_json(&file?.path()))
-        .flat_map(|item| item)
-        .collect())
+/// Add the stations and predictions from a directory of NOAA XML files.
+/// Stations that are already known, by id, keep their existing details.
+fn add_noaa_files(
+    mut stations: Vec<Station>,
+    mut predictions: Vec<PredictionsWithId>,
+    path: &Path,
+    report: &mut LoadReport,
+) -> (Vec<Station>, Vec<PredictionsWithId>) {
+    match crate::noaa_api::load_from_dir(path, &stations, report) {
+        Ok((noaa_stations, noaa_predictions)) => {
+            info!(
+                "Loaded {} stations from NOAA files in {}",
+                noaa_stations.len(),
+                path.display()
+            );
+            for station in noaa_stations {
+                if !stations.iter().any(|s| s.id == station.id) {
+                    stations.push(station);
+                }
+            }
+            predictions.extend(noaa_predictions);
+        }
+        Err(e) => warn!("No NOAA files loaded from {}: {}", path.display(), e),
+    }
+    (stations, predictions)
+}
+
+fn build_index(stations: &[Station]) -> SpatialIndex {
+    let coordinates: Vec<_> = stations.iter().map(|s| s.coordinates).collect();
+    SpatialIndex::new(&coordinates)
+}
+
+/// Read every JSON file of stations in a directory, noting anything that
+/// couldn't be loaded in `report`.
+pub fn load_stations_from_dir(
+    path: &Path,
+    report: &mut LoadReport,
+) -> Result<Vec<Station>, Box<dyn Error>> {
+    let mut stations = vec![];
+    for file in fs::read_dir(path)? {
+        let path = file?.path();
+        let file = report.file(&path);
+        match read_file(&path) {
+            Ok(src) => stations.extend(parse_stations(&src, file)),
+            Err(e) => file.skip(None, e),
+        }
+    }
+    Ok(stations)
 }
 
-fn load_predictions_from_json(path: &Path) -> Result<Vec<PredictionsWithId>, Box<dyn Error>> {
-    let string = read_file(&path)?;
-    Ok(parse_predictions(&string))
+/// Read every JSON file of station predictions in a directory, noting
+/// anything that couldn't be loaded in `report`.
+pub fn load_predictions_from_dir(
+    path: &Path,
+    report: &mut LoadReport,
+) -> Result<Vec<PredictionsWithId>, Box<dyn Error>> {
+    let mut predictions = vec![];
+    for file in fs::read_dir(path)? {
+        let path = file?.path();
+        let file = report.file(&path);
+        match read_file(&path) {
+            Ok(src) => predictions.extend(parse_predictions(&src, file)),
+            Err(e) => file.skip(None, e),
+        }
+    }
+    Ok(predictions)
 }
 
 fn read_file(path: &Path) -> Result<String, Box<dyn Error>> {
     let mut string = String::new();
-    let mut file = File::open(&path)?;
+    let mut file = File::open(path)?;
     file.read_to_string(&mut string)?;
     Ok(string)
 }
 
-fn parse_predictions(src: &str) -> Vec<PredictionsWithId> {
-    parse_vec_of_values(src)
-        .unwrap_or_else(|e| {
-            println!("Unable to parse string to vec of Values: {:?}", e);
-            vec![]
-        })
+/// The layouts a row of a predictions file can have.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PredictionsRow {
+    Many(PredictionsWithId),
+    /// One prediction per row, as in older data files.
+    Single {
+        station_id: Uuid,
+        prediction: TidePrediction,
+    },
+}
+
+fn parse_predictions(src: &str, file: &mut FileReport) -> Vec<PredictionsWithId> {
+    let mut predictions: Vec<PredictionsWithId> = vec![];
+    // Where each station's single predictions are being collected.
+    let mut singles: HashMap<Uuid, usize> = HashMap::new();
+    for (_, row) in parse_records(src, file) {
+        match row {
+            PredictionsRow::Many(many) => predictions.push(many),
+            PredictionsRow::Single {
+                station_id,
+                prediction,
+            } => {
+                let index = *singles.entry(station_id).or_insert_with(|| {
+                    predictions.push(PredictionsWithId {
+                        station_id,
+                        predictions: vec![],
+                    });
+                    predictions.len() - 1
+                });
+                predictions[index].predictions.push(prediction);
+            }
+        }
+    }
+    predictions
+}
+
+fn parse_stations(src: &str, file: &mut FileReport) -> Vec<Station> {
+    parse_records(src, file)
         .into_iter()
-        .filter_map(|v| {
-            let preds = serde_json::from_value(v);
-            if preds.is_err() {
-                println!(
-                    "Could not parse Value as PredictionsWithId instance: {:?}",
-                    preds
-                );
+        .map(|(position, mut station): (Position, Station)| {
+            if let Some(name) = &station.time_zone {
+                if Zone::from_name(name).is_none() {
+                    file.warn(
+                        Some(position),
+                        format!("unknown time zone {:?} for station {}", name, station.name),
+                    );
+                    station.time_zone = None;
+                }
             }
-            preds.ok()
+            station
         })
         .collect()
 }
 
-fn parse_stations(src: &str) -> Vec<Station> {
-    parse_vec_of_values(src)
-        .unwrap_or_else(|e| {
-            println!("Unable to parse string to vec of Values: {:?}", e);
-            vec![]
-        })
+/// Parse each record of a JSON array, or of line-delimited JSON, along
+/// with where it starts. Records that don't parse are skipped and noted
+/// in `file`.
+fn parse_records<T: DeserializeOwned>(src: &str, file: &mut FileReport) -> Vec<(Position, T)> {
+    let records = match split_records(src) {
+        Ok(records) => records,
+        Err((part, e)) => {
+            file.skip(Some(error_position(src, part, &e)), error_reason(&e));
+            return vec![];
+        }
+    };
+    records
         .into_iter()
-        .filter_map(|v| {
-            let station = serde_json::from_value(v);
-            if station.is_err() {
-                println!("Could not parse Value as Station instance: {:?}", station);
+        .filter_map(|raw| match serde_json::from_str(raw.get()) {
+            Ok(record) => {
+                file.loaded += 1;
+                Some((start_of(src, raw.get()), record))
+            }
+            Err(e) => {
+                file.skip(Some(error_position(src, raw.get(), &e)), error_reason(&e));
+                None
             }
-            station.ok()
         })
         .collect()
 }
 
-fn parse_vec_of_values(src: &str) -> Result<Vec<serde_json::Value>, serde_json::error::Error> {
-    serde_json::from_str(src)
+fn split_records(src: &str) -> Result<Vec<&RawValue>, (&str, serde_json::Error)> {
+    let records = serde_json::Deserializer::from_str(src)
+        .into_iter::<&RawValue>()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| (src, e))?;
+    match records.as_slice() {
+        [array] if array.get().starts_with('[') => {
+            serde_json::from_str(array.get()).map_err(|e| (array.get(), e))
+        }
+        _ => Ok(records),
+    }
+}
+
+/// Where `part`, a slice of `src`, starts.
+fn start_of(src: &str, part: &str) -> Position {
+    Position::of_offset(src, part.as_ptr() as usize - src.as_ptr() as usize)
+}
+
+/// Where in `src` an error in `part` of it happened.
+fn error_position(src: &str, part: &str, e: &serde_json::Error) -> Position {
+    start_of(src, part).offset_by(e.line(), e.column())
+}
+
+/// The error's message, without serde_json's position relative to `part`.
+fn error_reason(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+    match message.rfind(" at line ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use chrono::prelude::*;
-    use uom::si::f64::*;
     use uom::si::length::meter;
 
     #[test]
@@ -223,6 +661,288 @@ mod test {
         assert_eq!(catalogue.find_near(&yvr).name, "Point Atkinson");
     }
 
+    #[test]
+    fn test_finding_nearby_stations() {
+        let stations = load_stations_from_dir(
+            Path::new("test_data/stations/good"),
+            &mut LoadReport::default(),
+        )
+        .unwrap();
+        let catalogue = StationCatalogue::new(stations, vec![]);
+        let yvr = Coordinates {
+            lat: 49.194722,
+            lon: -123.183889,
+        };
+
+        let nearest = catalogue.find_k_nearest(&yvr, 5);
+        assert_eq!(nearest.len(), 5);
+        assert_eq!(nearest[0].0, catalogue.find_near(&yvr));
+        assert!(nearest.windows(2).all(|w| w[0].1 <= w[1].1));
+
+        let radius = Length::new::<kilometer>(25.0);
+        let within = catalogue.find_within(&yvr, radius);
+        assert!(!within.is_empty());
+        assert!(within.iter().all(|(_, d)| *d <= radius));
+        assert!(within.windows(2).all(|w| w[0].1 <= w[1].1));
+        let expected = catalogue
+            .stations
+            .iter()
+            .filter(|s| great_circle_distance(&s.coordinates, &yvr) <= radius)
+            .count();
+        assert_eq!(within.len(), expected);
+    }
+
+    #[test]
+    fn test_indexed_search_matches_a_linear_scan() {
+        let stations = load_stations_from_dir(
+            Path::new("test_data/stations/good"),
+            &mut LoadReport::default(),
+        )
+        .unwrap();
+        let catalogue = StationCatalogue::new(stations.clone(), vec![]);
+
+        for lat in (40..60).step_by(3) {
+            for lon in (-140..-50).step_by(7) {
+                let target = Coordinates {
+                    lat: f64::from(lat),
+                    lon: f64::from(lon),
+                };
+                let linear = stations
+                    .iter()
+                    .min_by(|a, b| {
+                        let da = great_circle_distance(&a.coordinates, &target);
+                        let db = great_circle_distance(&b.coordinates, &target);
+                        da.partial_cmp(&db).unwrap()
+                    })
+                    .unwrap();
+                assert_eq!(catalogue.find_near(&target).id, linear.id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_skipping_stations_without_predictions() {
+        let pst = FixedOffset::west(8 * 3600);
+        let prediction = |day, level| TidePrediction {
+            tide: Length::new::<meter>(level),
+            time: pst.ymd(2019, 5, day).and_hms(0, 0, 0),
+        };
+        let mut catalogue = StationCatalogue::empty();
+        catalogue.add("Empty", &Coordinates { lat: 0.0, lon: 0.0 }, &[]);
+        catalogue.add(
+            "Stale",
+            &Coordinates { lat: 0.0, lon: 0.3 },
+            &[prediction(1, 1.0), prediction(2, 2.0)],
+        );
+        catalogue.add(
+            "Current",
+            &Coordinates { lat: 0.0, lon: 0.6 },
+            &[prediction(14, 1.0), prediction(15, 2.0)],
+        );
+        let time = pst.ymd(2019, 5, 14).and_hms(12, 0, 0);
+
+        let (station, substitution) = catalogue.find_near_with_predictions(
+            &Coordinates {
+                lat: 0.0,
+                lon: 0.01,
+            },
+            time,
+        );
+        assert_eq!(station.name, "Current");
+        let substitution = substitution.unwrap();
+        assert_eq!(substitution.nearest.name, "Empty");
+        assert_eq!(substitution.reason, SubstitutionReason::NoPredictions);
+
+        let (station, substitution) = catalogue.find_near_with_predictions(
+            &Coordinates {
+                lat: 0.0,
+                lon: 0.29,
+            },
+            time,
+        );
+        assert_eq!(station.name, "Current");
+        assert_eq!(
+            substitution.unwrap().reason,
+            SubstitutionReason::PredictionsDontCoverTime
+        );
+
+        let (station, substitution) = catalogue.find_near_with_predictions(
+            &Coordinates {
+                lat: 0.0,
+                lon: 0.61,
+            },
+            time,
+        );
+        assert_eq!(station.name, "Current");
+        assert_eq!(substitution, None);
+
+        // Current is about 120 KM away, too far to stand in for Empty.
+        let (station, substitution) = catalogue.find_near_with_predictions(
+            &Coordinates {
+                lat: 0.0,
+                lon: -0.5,
+            },
+            time,
+        );
+        assert_eq!(station.name, "Empty");
+        assert_eq!(substitution, None);
+        assert_eq!(catalogue.pair_for_station(station, time), None);
+
+        let later = pst.ymd(2020, 1, 1).and_hms(0, 0, 0);
+        let (station, substitution) = catalogue.find_near_with_predictions(
+            &Coordinates {
+                lat: 0.0,
+                lon: 0.01,
+            },
+            later,
+        );
+        assert_eq!(station.name, "Empty");
+        assert_eq!(substitution, None);
+    }
+
+    #[test]
+    fn test_merging_prediction_series() {
+        let pst = FixedOffset::west(8 * 3600);
+        let prediction = |hour, level| TidePrediction {
+            tide: Length::new::<meter>(level),
+            time: pst.ymd(2019, 5, 14).and_hms(hour, 0, 0),
+        };
+        let station = Station {
+            name: "Merged".into(),
+            coordinates: Coordinates { lat: 0.0, lon: 0.0 },
+            id: Uuid::new_v4(),
+            time_zone: None,
+        };
+        let catalogue = StationCatalogue::new(
+            vec![station.clone()],
+            vec![
+                PredictionsWithId {
+                    station_id: station.id,
+                    predictions: vec![prediction(12, 3.0), prediction(0, 1.0)],
+                },
+                PredictionsWithId {
+                    station_id: station.id,
+                    predictions: vec![prediction(6, 2.0), prediction(12, 3.0), prediction(18, 4.0)],
+                },
+            ],
+        );
+
+        let series = catalogue.predictions_for_station(&station).unwrap();
+        let hours: Vec<_> = series.iter().map(|p| p.time.hour()).collect();
+        assert_eq!(hours, vec![0, 6, 12, 18]);
+
+        let time = pst.ymd(2019, 5, 14).and_hms(7, 0, 0);
+        assert_eq!(
+            catalogue.pair_for_station(&station, time),
+            Some(TidePredictionPair {
+                prev: prediction(6, 2.0),
+                next: prediction(12, 3.0),
+            })
+        );
+
+        let covering = catalogue.predictions_covering(
+            &station,
+            pst.ymd(2019, 5, 14).and_hms(7, 0, 0),
+            pst.ymd(2019, 5, 14).and_hms(13, 0, 0),
+        );
+        assert_eq!(
+            covering,
+            &[prediction(6, 2.0), prediction(12, 3.0), prediction(18, 4.0)]
+        );
+        let after = pst.ymd(2019, 5, 15).and_hms(0, 0, 0);
+        assert_eq!(
+            catalogue.predictions_covering(&station, after, after),
+            &[prediction(18, 4.0)]
+        );
+    }
+
+    #[test]
+    fn test_summarizing_the_catalogue() {
+        let utc = FixedOffset::east(0);
+        let prediction = |day| TidePrediction {
+            tide: Length::new::<meter>(1.0),
+            time: utc.ymd(2019, 5, day).and_hms(0, 0, 0),
+        };
+        let predictions = |days: &[u32]| PredictionsWithId {
+            station_id: Uuid::new_v4(),
+            predictions: days.iter().map(|day| prediction(*day)).collect(),
+        };
+        let series = vec![
+            predictions(&[3, 20]),
+            predictions(&[1, 10]),
+            predictions(&[]),
+        ];
+        let stations = series
+            .iter()
+            .map(|p| Station {
+                name: "Summarized".to_string(),
+                coordinates: Coordinates { lat: 0.0, lon: 0.0 },
+                id: p.station_id,
+                time_zone: None,
+            })
+            .collect();
+        // Predictions for a station that isn't in the catalogue don't count.
+        let orphan = predictions(&[2, 5]);
+        let catalogue =
+            StationCatalogue::new(stations, series.into_iter().chain(vec![orphan]).collect());
+
+        assert_eq!(
+            catalogue.summary(),
+            CatalogueSummary {
+                stations: 3,
+                prediction_series: 2,
+                earliest: Some(prediction(1).time),
+                latest: Some(prediction(20).time),
+                first_to_run_out: Some(prediction(10).time),
+            }
+        );
+        assert_eq!(StationCatalogue::empty().summary().latest, None);
+    }
+
+    #[test]
+    fn test_counting_stations_covered_until_a_time() {
+        let utc = FixedOffset::east(0);
+        let station = |name: &str| Station {
+            name: name.into(),
+            coordinates: Coordinates { lat: 0.0, lon: 0.0 },
+            id: Uuid::new_v4(),
+            time_zone: None,
+        };
+        let (short, long, none) = (station("Short"), station("Long"), station("None"));
+        let until = |station: &Station, day| PredictionsWithId {
+            station_id: station.id,
+            predictions: vec![TidePrediction {
+                tide: Length::new::<meter>(1.0),
+                time: utc.ymd(2019, 5, day).and_hms(0, 0, 0),
+            }],
+        };
+        let catalogue = StationCatalogue::new(
+            vec![short.clone(), long.clone(), none],
+            vec![until(&short, 5), until(&long, 20)],
+        );
+
+        assert_eq!(
+            catalogue.stations_covered_until(utc.ymd(2019, 5, 1).and_hms(0, 0, 0)),
+            2
+        );
+        assert_eq!(
+            catalogue.stations_covered_until(utc.ymd(2019, 5, 10).and_hms(0, 0, 0)),
+            1
+        );
+    }
+
+    #[test]
+    fn test_finding_stations_by_id() {
+        let catalogue = StationCatalogue::test();
+        let id = catalogue.stations[0].id;
+
+        assert_eq!(
+            catalogue.find_by_id(&id).map(|s| s.name.as_str()),
+            Some("Test Station")
+        );
+        assert_eq!(catalogue.find_by_id(&Uuid::nil()), None);
+    }
+
     #[test]
     fn test_finding_predictions_for_station() {
         let mut catalogue = StationCatalogue::empty();
@@ -268,34 +988,194 @@ mod test {
         #[test]
         fn it_should_handle_broken_files() {
             let path = Path::new("test_data/stations/broken_files");
-            let stations = load_stations_from_dir(&path);
+            let mut report = LoadReport::default();
+            let stations = load_stations_from_dir(&path, &mut report);
 
             assert_eq!(stations.is_ok(), true);
             let stations = stations.unwrap();
             assert_eq!(stations.len(), 1);
             assert_eq!(stations[0].name, "Point Atkinson");
+
+            assert!(!report.is_clean());
+            assert_eq!(report.loaded(), 1);
+            let broken_station = report
+                .files
+                .iter()
+                .find(|f| f.path.ends_with("broken_station.json"))
+                .unwrap();
+            assert_eq!(broken_station.loaded, 1);
+            assert_eq!(
+                broken_station.skipped[0].position,
+                Some(Position {
+                    line: 2,
+                    column: 30
+                })
+            );
+            assert!(broken_station.skipped[0].reason.contains("missing field"));
+        }
+
+        #[test]
+        fn it_should_load_an_empty_catalogue_from_missing_directories() {
+            let paths = DataPaths {
+                stations: PathBuf::from("test_data/missing/stations"),
+                predictions: PathBuf::from("test_data/missing/predictions"),
+                noaa: PathBuf::from("test_data/missing/noaa"),
+            };
+
+            let (catalogue, report) = StationCatalogue::load_with_report(&paths);
+
+            assert!(catalogue.is_empty());
+            assert_eq!(report.skipped(), 2);
+            assert_eq!(report.files[0].path, paths.stations);
+            assert!(StationCatalogue::try_load(&paths).is_err());
         }
 
         #[test]
         fn it_should_load_the_canadian_stations_file_without_error() {
             let path = Path::new("test_data/stations/good");
-            let stations = load_stations_from_dir(&path);
+            let stations = load_stations_from_dir(&path, &mut LoadReport::default());
 
             assert_eq!(stations.is_ok(), true);
             let stations = stations.unwrap();
             assert_eq!(stations.len(), 873);
         }
 
+        #[test]
+        fn it_should_add_stations_from_noaa_files() {
+            let existing = load_stations_from_dir(
+                Path::new("test_data/stations/good"),
+                &mut LoadReport::default(),
+            )
+            .unwrap();
+            let count = existing.len();
+
+            let (stations, predictions) = add_noaa_files(
+                existing,
+                vec![],
+                Path::new("test_data/noaa/good"),
+                &mut LoadReport::default(),
+            );
+            assert_eq!(stations.len(), count + 1);
+            assert!(!predictions.is_empty());
+
+            let (stations, _) = add_noaa_files(
+                stations,
+                predictions,
+                Path::new("test_data/noaa/good"),
+                &mut LoadReport::default(),
+            );
+            assert_eq!(stations.len(), count + 1);
+
+            let (stations, _) = add_noaa_files(
+                stations,
+                vec![],
+                Path::new("test_data/does_not_exist"),
+                &mut LoadReport::default(),
+            );
+            assert_eq!(stations.len(), count + 1);
+        }
+
         #[test]
         fn it_should_load_known_good_predictions_without_error() {
             let path = Path::new("test_data/predictions/good");
-            let preds = load_predictions_from_dir(path);
+            let preds = load_predictions_from_dir(path, &mut LoadReport::default());
 
             assert_eq!(preds.is_ok(), true);
             let preds = preds.unwrap();
             assert_eq!(preds.len(), 1);
             assert_eq!(preds[0].predictions.len(), 495);
         }
+
+        #[test]
+        fn it_should_load_single_prediction_rows_grouped_by_station() {
+            let path = Path::new("test_data/predictions/legacy");
+            let preds = load_predictions_from_dir(path, &mut LoadReport::default()).unwrap();
+
+            assert_eq!(preds.len(), 1);
+            assert_eq!(
+                preds[0].station_id,
+                Uuid::parse_str("946cc0d2-c976-423c-bb1e-89a400fbf8c1").unwrap()
+            );
+            assert_eq!(preds[0].predictions.len(), 4);
+            assert_eq!(preds[0].predictions[0].tide, Length::new::<meter>(0.053));
+        }
+
+        #[test]
+        fn it_should_parse_line_delimited_json() {
+            let src = r#"
+{"station_id": "946cc0d2-c976-423c-bb1e-89a400fbf8c1", "prediction": {"tide": 0.053, "time": "2019-06-28T04:34:00+00:00"}}
+{"station_id": "b8f8b6a8-6a4c-4bb5-8e0b-4b3b8b1b4a3a", "predictions": [{"tide": 1.0, "time": "2019-06-28T04:34:00+00:00"}]}
+{"station_id": "946cc0d2-c976-423c-bb1e-89a400fbf8c1", "prediction": {"tide": 0.239, "time": "2019-06-28T19:33:00+00:00"}}
+"#;
+            let preds = parse_predictions(src, &mut FileReport::default());
+
+            assert_eq!(preds.len(), 2);
+            assert_eq!(preds[0].predictions.len(), 2);
+            assert_eq!(preds[1].predictions.len(), 1);
+
+            let stations = parse_stations(
+                r#"{"name": "Point Atkinson", "id": "b8f8b6a8-6a4c-4bb5-8e0b-4b3b8b1b4a3a", "coordinates": {"lat": 49.3, "lon": -123.2}}
+{"name": "Port Lavaca", "id": "946cc0d2-c976-423c-bb1e-89a400fbf8c1", "coordinates": {"lat": 28.6, "lon": -96.6}}"#,
+                &mut FileReport::default(),
+            );
+            assert_eq!(stations.len(), 2);
+        }
+    }
+
+    mod time_zones {
+        use super::*;
+
+        #[test]
+        fn it_should_read_the_station_time_zone() {
+            let mut file = FileReport::default();
+            let stations = parse_stations(
+                r#"[
+                {"name": "Point Atkinson", "id": "a4793db4-0a47-38f0-bced-1615a179acfa",
+                 "coordinates": {"lat": 49.3299, "lon": -123.265}, "time_zone": "America/Vancouver"},
+                {"name": "Nowhere", "id": "29e0f2b5-d90d-32d6-9121-599287d3dae2",
+                 "coordinates": {"lat": 0.0, "lon": -123.265}, "time_zone": "Nowhere/Special"},
+                {"name": "Unzoned", "id": "59b126a9-2af0-39f9-a758-2bab1bcdf055",
+                 "coordinates": {"lat": 0.0, "lon": 30.0}}
+            ]"#,
+                &mut file,
+            );
+
+            assert_eq!(stations.len(), 3);
+            assert_eq!(file.warnings.len(), 1);
+            assert_eq!(
+                file.warnings[0].position,
+                Some(Position {
+                    line: 4,
+                    column: 17
+                })
+            );
+            assert_eq!(stations[0].time_zone, Some("America/Vancouver".to_string()));
+            assert_eq!(
+                stations[0].zone(),
+                Zone::from_name("America/Vancouver").unwrap()
+            );
+            assert_eq!(stations[1].time_zone, None);
+            assert_eq!(stations[2].zone(), Zone::Fixed(FixedOffset::east(2 * 3600)));
+        }
+
+        #[test]
+        fn it_should_give_shipped_stations_named_time_zones() {
+            let mut report = LoadReport::default();
+            let stations =
+                load_stations_from_dir(&DataPaths::default().stations, &mut report).unwrap();
+
+            assert!(report.is_clean());
+            let unzoned: Vec<_> = stations
+                .iter()
+                .filter(|s| !matches!(s.zone(), Zone::Named(_)))
+                .map(|s| &s.name)
+                .collect();
+            // Its coordinates are missing from the source data.
+            assert_eq!(unzoned, vec!["Dover-Wellington"]);
+
+            let halifax = stations.iter().find(|s| s.name == "Prospect").unwrap();
+            assert_eq!(halifax.zone(), Zone::from_name("America/Halifax").unwrap());
+        }
     }
 
     mod id_generation {
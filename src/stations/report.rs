@@ -0,0 +1,195 @@
+use log::{info, warn};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// What happened while loading the catalogue's data files.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct LoadReport {
+    pub files: Vec<FileReport>,
+}
+
+impl LoadReport {
+    /// Start reporting on a file. The returned report is added to this one.
+    pub fn file(&mut self, path: &Path) -> &mut FileReport {
+        self.files.push(FileReport {
+            path: path.to_path_buf(),
+            ..FileReport::default()
+        });
+        self.files.last_mut().expect("a file was just added")
+    }
+
+    /// True if every record in every file was loaded without complaint.
+    pub fn is_clean(&self) -> bool {
+        self.files.iter().all(FileReport::is_clean)
+    }
+
+    pub fn loaded(&self) -> usize {
+        self.files.iter().map(|f| f.loaded).sum()
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.files.iter().map(|f| f.skipped.len()).sum()
+    }
+
+    /// Log the summary, as a warning listing the problem files if there
+    /// are any.
+    pub fn log(&self) {
+        let text = self.to_string();
+        if self.is_clean() {
+            info!("{}", text.trim_end());
+        } else {
+            warn!("{}", text.trim_end());
+        }
+    }
+}
+
+/// Lists every file with problems, followed by a summary line.
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for file in self.files.iter().filter(|file| !file.is_clean()) {
+            writeln!(
+                f,
+                "{}: loaded {}, skipped {}",
+                file.path.display(),
+                file.loaded,
+                file.skipped.len()
+            )?;
+            for issue in &file.skipped {
+                writeln!(f, "  skipped {}", issue)?;
+            }
+            for issue in &file.warnings {
+                writeln!(f, "  warning {}", issue)?;
+            }
+        }
+        writeln!(
+            f,
+            "Loaded {} records from {} files, skipped {}",
+            self.loaded(),
+            self.files.len(),
+            self.skipped()
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// How many records (stations, or predictions rows) were loaded.
+    pub loaded: usize,
+    /// Records that couldn't be loaded. A file that can't be read or
+    /// parsed at all is one skipped record.
+    pub skipped: Vec<Issue>,
+    /// Records that were loaded, but not quite as written.
+    pub warnings: Vec<Issue>,
+}
+
+impl FileReport {
+    pub fn is_clean(&self) -> bool {
+        self.skipped.is_empty() && self.warnings.is_empty()
+    }
+
+    pub fn skip(&mut self, position: Option<Position>, reason: impl ToString) {
+        self.skipped.push(Issue {
+            position,
+            reason: reason.to_string(),
+        });
+    }
+
+    pub fn warn(&mut self, position: Option<Position>, reason: impl ToString) {
+        self.warnings.push(Issue {
+            position,
+            reason: reason.to_string(),
+        });
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Issue {
+    pub position: Option<Position>,
+    pub reason: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "at {}: {}", position, self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+/// A place in a file, counting lines and columns from 1.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    /// The position of a byte offset into `src`.
+    pub fn of_offset(src: &str, offset: usize) -> Self {
+        let before = &src[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Position {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    /// The position of `line` and `column` within a part of a file that
+    /// starts at this position.
+    pub fn offset_by(self, line: usize, column: usize) -> Self {
+        if line <= 1 {
+            Position {
+                line: self.line,
+                column: self.column + column.saturating_sub(1),
+            }
+        } else {
+            Position {
+                line: self.line + line - 1,
+                column,
+            }
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_find_positions_of_offsets() {
+        let src = "[\n  {\"a\": 1},\n  {\"b\": 2}\n]";
+        let offset = src.find("{\"b\"").unwrap();
+
+        let position = Position::of_offset(src, offset);
+
+        assert_eq!(position, Position { line: 3, column: 3 });
+        assert_eq!(position.offset_by(1, 5), Position { line: 3, column: 7 });
+        assert_eq!(position.offset_by(2, 5), Position { line: 4, column: 5 });
+    }
+
+    #[test]
+    fn it_should_summarize_problems() {
+        let mut report = LoadReport::default();
+        report.file(Path::new("good.json")).loaded = 3;
+        let bad = report.file(Path::new("bad.json"));
+        bad.loaded = 1;
+        bad.skip(Some(Position { line: 2, column: 1 }), "missing field `id`");
+
+        assert!(!report.is_clean());
+        assert_eq!(report.loaded(), 4);
+        assert_eq!(
+            report.to_string(),
+            "bad.json: loaded 1, skipped 1\n  \
+             skipped at line 2, column 1: missing field `id`\n\
+             Loaded 4 records from 2 files, skipped 1\n"
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use std::collections::HashMap;
+use std::fmt;
+use uom::si::length::meter;
+use uuid::Uuid;
+
+use crate::model::{Coordinates, TidePrediction};
+use crate::stations::{Station, StationCatalogue};
+
+/// Heights outside this range, in metres relative to chart datum, are
+/// almost certainly a unit or parsing mistake. The largest tides in the
+/// world, in the Bay of Fundy, stay well inside it.
+const PLAUSIBLE_HEIGHTS_M: (f64, f64) = (-5.0, 20.0);
+
+/// Heights closer than this, in metres, count as the same when checking
+/// that highs and lows take turns. Many series are published to the
+/// nearest 0.1 m, so a real turn can show up as a tie or a 0.1 m step.
+const SAME_HEIGHT_M: f64 = 0.15;
+
+/// Longest believable time between one high or low tide and the next.
+fn max_gap() -> Duration {
+    Duration::days(1)
+}
+
+/// Something wrong with the catalogue's data.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Problem {
+    CoordinatesOutOfRange {
+        station: String,
+        coordinates: Coordinates,
+    },
+    DuplicateId {
+        id: Uuid,
+        count: usize,
+    },
+    /// Stations with different ids but the same name and location.
+    DuplicateStation {
+        name: String,
+        coordinates: Coordinates,
+        ids: Vec<Uuid>,
+    },
+    PredictionsForUnknownStation {
+        station_id: Uuid,
+    },
+    /// Highs and lows should take turns. `count` is how many predictions
+    /// were neither higher nor lower than both neighbours.
+    NotAlternating {
+        station: String,
+        first: DateTime<FixedOffset>,
+        count: usize,
+    },
+    ImplausibleHeight {
+        station: String,
+        time: DateTime<FixedOffset>,
+        height_m: f64,
+    },
+    Gap {
+        station: String,
+        from: DateTime<FixedOffset>,
+        to: DateTime<FixedOffset>,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::CoordinatesOutOfRange {
+                station,
+                coordinates,
+            } => write!(
+                f,
+                "{}: coordinates out of range ({}, {})",
+                station, coordinates.lat, coordinates.lon
+            ),
+            Problem::DuplicateId { id, count } => {
+                write!(f, "{} stations share the id {}", count, id)
+            }
+            Problem::DuplicateStation {
+                name,
+                coordinates,
+                ids,
+            } => write!(
+                f,
+                "{} stations named {:?} at ({}, {}): {}",
+                ids.len(),
+                name,
+                coordinates.lat,
+                coordinates.lon,
+                ids.iter()
+                    .map(Uuid::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Problem::PredictionsForUnknownStation { station_id } => {
+                write!(f, "predictions for unknown station {}", station_id)
+            }
+            Problem::NotAlternating {
+                station,
+                first,
+                count,
+            } => write!(
+                f,
+                "{}: {} predictions don't alternate between high and low, first at {}",
+                station,
+                count,
+                first.to_rfc3339()
+            ),
+            Problem::ImplausibleHeight {
+                station,
+                time,
+                height_m,
+            } => write!(
+                f,
+                "{}: implausible height {}m at {}",
+                station,
+                height_m,
+                time.to_rfc3339()
+            ),
+            Problem::Gap { station, from, to } => write!(
+                f,
+                "{}: no predictions between {} and {}",
+                station,
+                from.to_rfc3339(),
+                to.to_rfc3339()
+            ),
+        }
+    }
+}
+
+/// Check the catalogue's stations and predictions for data that's
+/// probably wrong. An empty result means nothing was found.
+pub fn validate(catalogue: &StationCatalogue) -> Vec<Problem> {
+    let mut problems = vec![];
+    let stations = &catalogue.stations;
+
+    for station in stations {
+        if !in_range(&station.coordinates) {
+            problems.push(Problem::CoordinatesOutOfRange {
+                station: describe(&station.name, station.id),
+                coordinates: station.coordinates,
+            });
+        }
+    }
+
+    let mut by_id: HashMap<Uuid, usize> = HashMap::new();
+    for station in stations {
+        *by_id.entry(station.id).or_default() += 1;
+    }
+    let mut by_place: HashMap<(&str, u64, u64), Vec<Uuid>> = HashMap::new();
+    for station in stations {
+        let ids = by_place.entry(place(station)).or_default();
+        if !ids.contains(&station.id) {
+            ids.push(station.id);
+        }
+    }
+    // Walk the stations rather than the maps so problems come out in order.
+    for station in stations {
+        if let Some(count) = by_id.remove(&station.id).filter(|count| *count > 1) {
+            problems.push(Problem::DuplicateId {
+                id: station.id,
+                count,
+            });
+        }
+        if let Some(ids) = by_place.remove(&place(station)).filter(|ids| ids.len() > 1) {
+            problems.push(Problem::DuplicateStation {
+                name: station.name.clone(),
+                coordinates: station.coordinates,
+                ids,
+            });
+        }
+    }
+
+    let names: HashMap<Uuid, &str> = stations.iter().map(|s| (s.id, s.name.as_str())).collect();
+    let mut station_ids: Vec<&Uuid> = catalogue.predictions.keys().collect();
+    station_ids.sort();
+    for station_id in station_ids {
+        match names.get(station_id) {
+            Some(name) => problems.extend(validate_predictions(
+                &describe(name, *station_id),
+                &catalogue.predictions[station_id],
+            )),
+            None => problems.push(Problem::PredictionsForUnknownStation {
+                station_id: *station_id,
+            }),
+        }
+    }
+    problems
+}
+
+/// Check one station's predictions, which must be sorted by time.
+fn validate_predictions(station: &str, predictions: &[TidePrediction]) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    for prediction in predictions {
+        let height_m = prediction.tide.get::<meter>();
+        if !(PLAUSIBLE_HEIGHTS_M.0..=PLAUSIBLE_HEIGHTS_M.1).contains(&height_m) {
+            problems.push(Problem::ImplausibleHeight {
+                station: station.to_string(),
+                time: prediction.time,
+                height_m,
+            });
+        }
+    }
+
+    let not_alternating: Vec<_> = predictions
+        .windows(3)
+        .filter(|w| {
+            let into = (w[1].tide - w[0].tide).get::<meter>();
+            let out = (w[2].tide - w[1].tide).get::<meter>();
+            (into > SAME_HEIGHT_M && out > SAME_HEIGHT_M)
+                || (into < -SAME_HEIGHT_M && out < -SAME_HEIGHT_M)
+        })
+        .map(|w| w[1].time)
+        .collect();
+    if let Some(first) = not_alternating.first() {
+        problems.push(Problem::NotAlternating {
+            station: station.to_string(),
+            first: *first,
+            count: not_alternating.len(),
+        });
+    }
+
+    for pair in predictions.windows(2) {
+        if pair[1].time.signed_duration_since(pair[0].time) > max_gap() {
+            problems.push(Problem::Gap {
+                station: station.to_string(),
+                from: pair[0].time,
+                to: pair[1].time,
+            });
+        }
+    }
+    problems
+}
+
+fn in_range(coordinates: &Coordinates) -> bool {
+    (-90.0..=90.0).contains(&coordinates.lat) && (-180.0..=180.0).contains(&coordinates.lon)
+}
+
+/// Stations are in the same place if they have the same name and exactly
+/// the same coordinates.
+fn place(station: &Station) -> (&str, u64, u64) {
+    (
+        station.name.as_str(),
+        station.coordinates.lat.to_bits(),
+        station.coordinates.lon.to_bits(),
+    )
+}
+
+fn describe(name: &str, id: Uuid) -> String {
+    format!("{} ({})", name, id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stations::PredictionsWithId;
+    use uom::si::f64::*;
+
+    fn station(name: &str, lat: f64, lon: f64) -> Station {
+        Station {
+            name: name.to_string(),
+            coordinates: Coordinates { lat, lon },
+            id: Uuid::new_v4(),
+            time_zone: None,
+        }
+    }
+
+    /// Predictions six hours apart with the given heights.
+    fn predictions(station: &Station, heights: &[f64]) -> PredictionsWithId {
+        let start = FixedOffset::east(0).ymd(2019, 1, 1).and_hms(0, 0, 0);
+        PredictionsWithId {
+            station_id: station.id,
+            predictions: heights
+                .iter()
+                .enumerate()
+                .map(|(i, height)| TidePrediction {
+                    tide: Length::new::<meter>(*height),
+                    time: start + Duration::hours(6 * i as i64),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn it_should_accept_good_data() {
+        let good = station("Good", 49.0, -123.0);
+        let preds = predictions(&good, &[1.0, 3.0, 0.5, 3.5, 1.0]);
+        let catalogue = StationCatalogue::new(vec![good], vec![preds]);
+
+        assert_eq!(validate(&catalogue), vec![]);
+    }
+
+    #[test]
+    fn it_should_flag_bad_stations() {
+        let far_north = station("Far North", 91.0, 0.0);
+        let first = station("Twin", 10.0, 10.0);
+        let second = station("Twin", 10.0, 10.0);
+        let copy = first.clone();
+        let orphan = Uuid::new_v4();
+        let catalogue = StationCatalogue::new(
+            vec![far_north.clone(), first.clone(), second.clone(), copy],
+            vec![PredictionsWithId {
+                station_id: orphan,
+                predictions: predictions(&first, &[1.0]).predictions,
+            }],
+        );
+
+        assert_eq!(
+            validate(&catalogue),
+            vec![
+                Problem::CoordinatesOutOfRange {
+                    station: describe("Far North", far_north.id),
+                    coordinates: far_north.coordinates,
+                },
+                Problem::DuplicateId {
+                    id: first.id,
+                    count: 2
+                },
+                Problem::DuplicateStation {
+                    name: "Twin".to_string(),
+                    coordinates: first.coordinates,
+                    ids: vec![first.id, second.id],
+                },
+                Problem::PredictionsForUnknownStation { station_id: orphan },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_flag_bad_predictions() {
+        let bad = station("Bad", 49.0, -123.0);
+        let mut preds = predictions(&bad, &[1.0, 3.0, 3.5, 0.5, 30.0]);
+        let last = preds.predictions.len() - 1;
+        preds.predictions[last].time = preds.predictions[last].time + Duration::days(2);
+        let name = describe("Bad", bad.id);
+
+        let problems = validate_predictions(&name, &preds.predictions);
+
+        assert_eq!(
+            problems,
+            vec![
+                Problem::ImplausibleHeight {
+                    station: name.clone(),
+                    time: preds.predictions[4].time,
+                    height_m: 30.0,
+                },
+                Problem::NotAlternating {
+                    station: name.clone(),
+                    first: preds.predictions[1].time,
+                    count: 1,
+                },
+                Problem::Gap {
+                    station: name.clone(),
+                    from: preds.predictions[3].time,
+                    to: preds.predictions[4].time,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_accept_tides_that_round_to_the_same_height() {
+        // Esquimalt's high, low and high on 2019-10-05, to the nearest 0.1 m.
+        let esquimalt = station("Esquimalt", 48.4, -123.4);
+        let preds = predictions(&esquimalt, &[0.9, 2.5, 2.4, 2.4, 0.6]);
+
+        let problems = validate_predictions("Esquimalt", &preds.predictions);
+
+        assert_eq!(problems, vec![]);
+    }
+}
@@ -0,0 +1,112 @@
+use chrono::prelude::*;
+use chrono_tz::Tz;
+
+use crate::model::Coordinates;
+
+/// A time zone that times can be displayed in.
+///
+/// Named zones know about daylight saving time, so every time is shown with
+/// the offset that was in effect at that instant. Fixed offsets are kept
+/// for clients that only tell us their current UTC offset.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Zone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    /// Look up an IANA time zone name such as `America/Vancouver`.
+    pub fn from_name(name: &str) -> Option<Zone> {
+        name.parse::<Tz>().ok().map(Zone::Named)
+    }
+
+    /// A zone for a client that reports its offset the way javascript's
+    /// `Date.getTimezoneOffset` does: minutes *behind* UTC.
+    pub fn from_minutes_west(minutes: i32) -> Option<Zone> {
+        minutes
+            .checked_mul(60)
+            .and_then(FixedOffset::west_opt)
+            .map(Zone::Fixed)
+    }
+
+    /// Nautical time zone for a point: the offset from UTC in whole hours
+    /// based only on longitude. This ignores political boundaries, so it's
+    /// only a fallback for when nothing better is known.
+    pub fn nautical(coords: &Coordinates) -> Zone {
+        let hours = (coords.lon / 15.0).round().clamp(-12.0, 12.0) as i32;
+        Zone::Fixed(FixedOffset::east(hours * 3600))
+    }
+
+    /// The UTC offset in effect in this zone at the given instant.
+    pub fn offset_at<T: TimeZone>(&self, time: &DateTime<T>) -> FixedOffset {
+        match self {
+            Zone::Named(tz) => tz.offset_from_utc_datetime(&time.naive_utc()).fix(),
+            Zone::Fixed(offset) => *offset,
+        }
+    }
+
+    /// Express `time` in this zone.
+    pub fn localize<T: TimeZone>(&self, time: &DateTime<T>) -> DateTime<FixedOffset> {
+        time.with_timezone(&self.offset_at(time))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Zone::Named(tz) => tz.name().to_string(),
+            Zone::Fixed(offset) => format!("UTC{}", offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_apply_daylight_saving_time() {
+        let vancouver = Zone::from_name("America/Vancouver").unwrap();
+        let winter = Utc.ymd(2019, 3, 10).and_hms(9, 0, 0);
+        let summer = Utc.ymd(2019, 3, 10).and_hms(11, 0, 0);
+
+        assert_eq!(vancouver.offset_at(&winter), FixedOffset::west(8 * 3600));
+        assert_eq!(vancouver.offset_at(&summer), FixedOffset::west(7 * 3600));
+        assert_eq!(vancouver.localize(&summer).hour(), 4);
+    }
+
+    #[test]
+    fn it_should_reject_unknown_zone_names() {
+        assert_eq!(Zone::from_name("Mars/Olympus_Mons"), None);
+        assert_eq!(Zone::from_name(""), None);
+    }
+
+    #[test]
+    fn it_should_understand_javascript_offsets() {
+        assert_eq!(
+            Zone::from_minutes_west(420),
+            Some(Zone::Fixed(FixedOffset::west(7 * 3600)))
+        );
+        assert_eq!(Zone::from_minutes_west(100 * 60), None);
+        assert_eq!(Zone::from_minutes_west(50_000_000), None);
+        assert_eq!(Zone::from_minutes_west(i32::MIN), None);
+    }
+
+    #[test]
+    fn it_should_approximate_zones_from_longitude() {
+        let vancouver = Coordinates {
+            lat: 49.28,
+            lon: -123.12,
+        };
+        assert_eq!(
+            Zone::nautical(&vancouver),
+            Zone::Fixed(FixedOffset::west(8 * 3600))
+        );
+        let dateline = Coordinates {
+            lat: 0.0,
+            lon: 179.9,
+        };
+        assert_eq!(
+            Zone::nautical(&dateline),
+            Zone::Fixed(FixedOffset::east(12 * 3600))
+        );
+    }
+}